@@ -0,0 +1,79 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use embedded_gif::data_source::{DataSource, SliceSource};
+use embedded_gif::frame_decoder::{ImageArea, LzwEntry};
+use embedded_gif::gif_decoder::GifDecoder;
+use embedded_gif::gif_error::Error;
+use embedded_gif::pixel_format::{ColorTable, Rgb565};
+use embedded_gif::renderer::ImageRenderer;
+
+const REVERSE_BUF_LEN: usize = 512;
+const OUT_BUF_LEN: usize = 240 * 20;
+
+/// only looks at the output, so that the decoding dominates
+struct NullRenderer {
+    checksum: u32,
+}
+
+impl ImageRenderer for NullRenderer {
+    type Format = Rgb565;
+    type Error = Error;
+
+    fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        _color_table: &ColorTable<Rgb565>,
+        _transparency_index: Option<u8>,
+    ) -> Result<(), Error> {
+        let len = area.width as usize * area.height as usize;
+        self.checksum = self.checksum.wrapping_add(buffer[len - 1] as u32);
+        Ok(())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+fn decode<DS: DataSource>(data_source: DS) -> u32 {
+    let mut renderer = NullRenderer { checksum: 0 };
+
+    let mut buf_a = [0u16; 256];
+    let mut buf_b = [0u16; 256];
+    let mut buf_c = [LzwEntry::default(); 4096];
+    let mut buf_d = [0u8; REVERSE_BUF_LEN];
+    let mut buf_e = [0u8; OUT_BUF_LEN];
+
+    let mut decoder = GifDecoder::new(
+        data_source,
+        &mut renderer,
+        &mut buf_a,
+        &mut buf_b,
+        &mut buf_c,
+        &mut buf_d,
+        &mut buf_e,
+    );
+
+    decoder.parse_gif_metadata().unwrap();
+    while let Some(frame) = decoder.next_frame() {
+        frame.unwrap();
+        decoder.decode_frame_image().unwrap();
+    }
+    renderer.checksum
+}
+
+fn data_sources(c: &mut Criterion) {
+    let bytes = std::fs::read("./tests/gifs/test_cat_small.gif").unwrap();
+    let mut group = c.benchmark_group("test_cat_small");
+
+    group.bench_function("iterator", |b| {
+        b.iter(|| decode(black_box(bytes.as_slice()).iter().copied()))
+    });
+    group.bench_function("slice", |b| {
+        b.iter(|| decode(SliceSource::new(black_box(bytes.as_slice()))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, data_sources);
+criterion_main!(benches);
@@ -0,0 +1,357 @@
+use crate::frame_decoder::{GraphicsControlExtension, ImageArea, LzwEntry, LzwOutput};
+use crate::gif_decoder::{
+    is_looping_extension, parse_block_introducer, parse_extension_label,
+    parse_graphics_control_extension, parse_header, parse_image_descriptor,
+    parse_logical_screen_descriptor, Block, DecoderState, Extension, GifFileMetadata,
+};
+use crate::gif_error::{DecodeError, Error, Failure};
+use crate::pixel_format::{ColorTable, PixelFormat};
+use crate::renderer::RenderAction;
+use embedded_io_async::Read;
+
+/// Async variant of ImageRenderer, e.g. for display drivers that transfer the
+/// areas with DMA. See ImageRenderer for the meaning of the methods.
+#[allow(async_fn_in_trait)]
+pub trait AsyncImageRenderer {
+    /// pixel format the color tables are converted into
+    type Format: PixelFormat;
+
+    /// error of the display or bus, returned as Failure::Render in DecodeError
+    type Error;
+
+    async fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<Self::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), Self::Error>;
+
+    async fn flush_frame(&mut self) -> Result<(), Self::Error>;
+
+    async fn save_area(&mut self, _area: ImageArea) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn clear_area(&mut self, _area: ImageArea) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn fill_area(
+        &mut self,
+        _area: ImageArea,
+        _color: <Self::Format as PixelFormat>::Pixel,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn restore_area(&mut self, _area: ImageArea) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+async fn apply_action<R: AsyncImageRenderer>(
+    renderer: &mut R,
+    action: RenderAction<<R::Format as PixelFormat>::Pixel>,
+) -> Result<(), R::Error> {
+    match action {
+        RenderAction::Save(area) => renderer.save_area(area).await,
+        RenderAction::Clear(area) => renderer.clear_area(area).await,
+        RenderAction::Fill(area, color) => renderer.fill_area(area, color).await,
+        RenderAction::Restore(area) => renderer.restore_area(area).await,
+    }
+}
+
+/// Reads the data source in blocks of the size of the read buffer
+struct ReadBuffer<'a, DS> {
+    reader: DS,
+    buffer: &'a mut [u8],
+    start: usize,
+    end: usize,
+    /// offset in the file of the first unconsumed byte
+    offset: usize,
+}
+
+impl<'a, DS: Read> ReadBuffer<'a, DS> {
+    /// the unconsumed bytes of the buffer, reads the next block if there are none
+    async fn fill(&mut self) -> Result<&[u8], Error> {
+        if self.start == self.end {
+            if self.buffer.is_empty() {
+                return Err(Error::ReadError);
+            }
+            let count = self
+                .reader
+                .read(self.buffer)
+                .await
+                .map_err(|_| Error::ReadError)?;
+
+            if count == 0 {
+                return Err(Error::FileEnded);
+            }
+            self.start = 0;
+            self.end = count;
+        }
+        Ok(&self.buffer[self.start..self.end])
+    }
+
+    fn consume(&mut self, count: usize) {
+        self.start += count;
+        self.offset += count;
+    }
+
+    async fn next_byte(&mut self) -> Result<u8, Error> {
+        let byte = self.fill().await?[0];
+        self.consume(1);
+        Ok(byte)
+    }
+
+    async fn next_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut bytes = [0u8; N];
+        for byte in bytes.iter_mut() {
+            *byte = self.next_byte().await?;
+        }
+        Ok(bytes)
+    }
+
+    /// consumes data sub-blocks up to and including the block terminator
+    async fn skip_sub_blocks(&mut self, first_block_size: u8) -> Result<(), Error> {
+        let mut block_size = first_block_size;
+
+        while block_size != 0 {
+            let mut remaining = block_size as usize;
+            while remaining > 0 {
+                let count = self.fill().await?.len().min(remaining);
+                self.consume(count);
+                remaining -= count;
+            }
+            block_size = self.next_byte().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Async variant of GifDecoder for embedded-io-async readers and AsyncImageRenderers.
+/// While the reader or the renderer are waiting, e.g. for a flash read or a display
+/// transfer, the executor can run other tasks.
+///
+/// The data source is read in blocks into the read buffer, like ReadSource does for
+/// GifDecoder. With an empty read buffer every read fails with Error::ReadError.
+/// Usage, compositing and the other buffers are the same as for GifDecoder.
+pub struct AsyncGifDecoder<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+where
+    R: AsyncImageRenderer,
+{
+    data_source: ReadBuffer<'a, DS>,
+    renderer: &'a mut R,
+    state: DecoderState<'a, R::Format, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+}
+
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    AsyncGifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: Read,
+    R: AsyncImageRenderer,
+{
+    /// buffers need to be passed in from outside so that this object still fits on the stack
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        data_source: DS,
+        read_buffer: &'a mut [u8],
+        renderer: &'a mut R,
+        buf_a: &'a mut ColorTable<R::Format>,
+        buf_b: &'a mut ColorTable<R::Format>,
+        buf_c: &'a mut [LzwEntry; 4096],
+        buf_d: &'a mut [u8; REVERSE_BUF_LEN],
+        buf_e: &'a mut [u8; OUT_BUF_LEN],
+    ) -> Self {
+        AsyncGifDecoder {
+            data_source: ReadBuffer {
+                reader: data_source,
+                buffer: read_buffer,
+                start: 0,
+                end: 0,
+                offset: 0,
+            },
+            renderer,
+            state: DecoderState::new(buf_a, buf_b, buf_c, buf_d, buf_e),
+        }
+    }
+
+    /// Enables honouring the disposal methods of frames, see ImageRenderer::clear_area()
+    pub fn set_compositing(&mut self, enabled: bool) {
+        self.state.compositing = enabled;
+        self.state.pending_disposal = None;
+    }
+
+    /// Enables filling with the background color of the file, see ImageRenderer::fill_area().
+    /// Needs to be set before parse_gif_metadata() is called.
+    pub fn set_fill_background(&mut self, enabled: bool) {
+        self.state.fill_background = enabled;
+    }
+
+    async fn parse_color_table(&mut self, size: usize, local: bool) -> Result<(), Error> {
+        for i in 0..size {
+            let [r, g, b] = self.data_source.next_bytes().await?;
+            self.state.set_color(local, i, r, g, b);
+        }
+        Ok(())
+    }
+
+    fn locate(&self, error: impl Into<Failure<R::Error>>) -> DecodeError<R::Error> {
+        self.state.error_at(error, self.data_source.offset)
+    }
+
+    /// Parses and consumes the initial metadata section of a GIF file
+    pub async fn parse_gif_metadata(&mut self) -> Result<(), DecodeError<R::Error>> {
+        self.state.frame = None;
+        let result = self.read_gif_metadata().await;
+        result.map_err(|error| self.locate(error))
+    }
+
+    async fn read_gif_metadata(&mut self) -> Result<(), Failure<R::Error>> {
+        let version = parse_header(&self.data_source.next_bytes().await?)?;
+        let descriptor = self.data_source.next_bytes().await?;
+        let metadata = parse_logical_screen_descriptor(version, &descriptor);
+        DecoderState::<R::Format, OUT_BUF_LEN, REVERSE_BUF_LEN>::check_width(metadata.width)?;
+
+        if metadata.has_global_color_table {
+            self.parse_color_table(metadata.global_color_table_size, false)
+                .await?;
+        }
+        match self.state.set_file_metadata(metadata) {
+            Some(action) => apply_action(self.renderer, action)
+                .await
+                .map_err(Failure::Render),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_gif_metadata(&self) -> Option<&GifFileMetadata<<R::Format as PixelFormat>::Pixel>> {
+        self.state.file_metadata.as_ref()
+    }
+
+    /// See GIF 89a spec section 26. Only the NETSCAPE2.0 looping extension is used,
+    /// everything else is skipped.
+    /// Extension Introducer, label and block size already handled by caller
+    async fn parse_application_extension(&mut self) -> Result<(), Error> {
+        let identifier = self.data_source.next_bytes().await?;
+
+        let block_size = self.data_source.next_byte().await?;
+        if !is_looping_extension(&identifier, block_size) {
+            return self.data_source.skip_sub_blocks(block_size).await;
+        }
+        let sub_block = self.data_source.next_bytes().await?;
+        self.state.set_loop_count(&sub_block);
+
+        let block_size = self.data_source.next_byte().await?;
+        self.data_source.skip_sub_blocks(block_size).await
+    }
+
+    /// Parses and consumes the metadata section of the next frame, including all
+    /// GIF extensions up until the actual image data.
+    /// Returns Err(Error::GifEnded) when there is no frame left
+    pub async fn parse_frame_metadata(&mut self) -> Result<(), DecodeError<R::Error>> {
+        self.state.begin_frame();
+        let result = self.read_frame_metadata().await;
+        result.map_err(|error| self.locate(error))
+    }
+
+    async fn read_frame_metadata(&mut self) -> Result<(), Error> {
+        let mut extension: Option<GraphicsControlExtension> = None;
+
+        loop {
+            match parse_block_introducer(self.data_source.next_byte().await?)? {
+                Block::ImageDescriptor => {
+                    let descriptor = self.data_source.next_bytes().await?;
+                    let metadata = parse_image_descriptor(&descriptor, extension);
+                    DecoderState::<R::Format, OUT_BUF_LEN, REVERSE_BUF_LEN>::check_width(
+                        metadata.frame_area.width,
+                    )?;
+
+                    if metadata.has_local_color_table {
+                        self.parse_color_table(metadata.local_color_table_size, true)
+                            .await?;
+                    }
+                    self.state.current_frame_metadata = Some(metadata);
+
+                    return Ok(()); // image data follows
+                }
+                Block::Extension => {
+                    let [extension_label, block_size] = self.data_source.next_bytes().await?;
+
+                    match parse_extension_label(extension_label, block_size) {
+                        Extension::GraphicsControl => {
+                            let block = self.data_source.next_bytes().await?;
+                            extension = Some(parse_graphics_control_extension(&block)?);
+                        }
+                        Extension::Application => self.parse_application_extension().await?,
+                        Extension::Skipped => self.data_source.skip_sub_blocks(block_size).await?,
+                    }
+                }
+                Block::Trailer => return Err(Error::GifEnded),
+            }
+        }
+    }
+
+    pub fn get_current_frame_delay_ms(&self) -> Option<u32> {
+        self.state.current_frame_delay_ms()
+    }
+
+    /// Decodes and consumes the image data of the frame.
+    /// Awaits renderer.write_area() whenever the output buffer is full.
+    /// Awaits renderer.flush_frame() when all images data has been written.
+    pub async fn decode_frame_image(&mut self) -> Result<(), DecodeError<R::Error>> {
+        let result = self.decode_image_data().await;
+        result.map_err(|error| self.locate(error))
+    }
+
+    async fn decode_image_data(&mut self) -> Result<(), Failure<R::Error>> {
+        let initial_lzw_size = self.data_source.next_byte().await?;
+        let (mut lzw_state, actions) = self.state.start_frame_image(initial_lzw_size)?;
+        for action in actions.into_iter().flatten() {
+            apply_action(self.renderer, action)
+                .await
+                .map_err(Failure::Render)?;
+        }
+
+        let (mut decoder, color_table) = self.state.lzw_decoder(&mut lzw_state)?;
+        let mut block_size = self.data_source.next_byte().await?;
+
+        while block_size != 0 {
+            let mut remaining = block_size as usize;
+
+            while remaining > 0 {
+                let bytes = self.data_source.fill().await?;
+                let count = bytes.len().min(remaining);
+                let mut input = &bytes[..count];
+
+                loop {
+                    match decoder.next_output(&mut input)? {
+                        LzwOutput::NeedInput => break,
+                        LzwOutput::Rows(area) => self
+                            .renderer
+                            .write_area(
+                                area,
+                                decoder.output_buffer(),
+                                color_table,
+                                decoder.transparency_index(),
+                            )
+                            .await
+                            .map_err(Failure::Render)?,
+                        LzwOutput::Finished => {
+                            self.renderer.flush_frame().await.map_err(Failure::Render)?
+                        }
+                    }
+                }
+                self.data_source.consume(count);
+                remaining -= count;
+            }
+            block_size = self.data_source.next_byte().await?;
+        }
+        Ok(())
+    }
+
+    pub fn get_data_source(&mut self) -> &mut DS {
+        &mut self.data_source.reader
+    }
+}
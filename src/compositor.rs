@@ -0,0 +1,271 @@
+use crate::frame_decoder::ImageArea;
+use crate::pixel_format::{ColorTable, PixelFormat};
+use crate::renderer::{ImageRenderer, PixelRenderer};
+
+type Pixel<R> = <<R as PixelRenderer>::Format as PixelFormat>::Pixel;
+
+/// Renderer that composes the frames on a canvas of the size of the logical screen,
+/// for displays that can not keep the previous frame themselves.
+/// Frames are drawn over the canvas skipping transparent pixels, the disposal calls of
+/// the decoder act on the canvas. flush_frame() then sends the region of the canvas that
+/// changed during the frame to the downstream PixelRenderer: the bounding box of the
+/// pixels that got a different color, so that e.g. a blinking cursor only costs the
+/// bandwidth of the cursor. Pixels that are changed by the disposal and set back by the
+/// next frame still count as changed.
+///
+/// The decoder has to be in compositing mode, see GifDecoder::set_compositing().
+/// Pixels outside of the canvas are dropped.
+pub struct Compositor<'c, R: PixelRenderer> {
+    renderer: R,
+    canvas: &'c mut [Pixel<R>],
+    saved: Option<&'c mut [Pixel<R>]>,
+    width: u16,
+    height: u16,
+    clear_color: Pixel<R>,
+    changed: Option<ImageArea>,
+}
+
+impl<'c, R> Compositor<'c, R>
+where
+    R: PixelRenderer,
+    Pixel<R>: PartialEq,
+{
+    /// The canvas holds width * height pixels row by row, a shorter canvas cuts off the
+    /// bottom rows. It is cleared, the first flush_frame() sends all of it.
+    pub fn new(renderer: R, canvas: &'c mut [Pixel<R>], width: u16, height: u16) -> Self {
+        let rows = canvas.len().checked_div(width as usize).unwrap_or(0);
+        let mut compositor = Self {
+            renderer,
+            canvas,
+            saved: None,
+            width,
+            height: height.min(rows.min(u16::MAX as usize) as u16),
+            clear_color: Pixel::<R>::default(),
+            changed: None,
+        };
+        compositor.clear();
+        compositor
+    }
+
+    /// Buffer of the same size as the canvas that keeps the areas of RestoreToPrevious
+    /// frames. Without it these frames are not disposed.
+    pub fn set_save_buffer(&mut self, saved: &'c mut [Pixel<R>]) {
+        self.saved = Some(saved);
+    }
+
+    /// color of the areas cleared by RestoreToBackground, the default pixel otherwise
+    pub fn set_clear_color(&mut self, color: Pixel<R>) {
+        self.clear_color = color;
+    }
+
+    /// Fills the whole canvas with the clear color, e.g. before looping the animation.
+    /// The next flush_frame() sends all of it, whatever was on the canvas before.
+    pub fn clear(&mut self) {
+        let screen = self.screen_area();
+        self.fill(screen, self.clear_color);
+        if let Some(screen) = self.clip(screen) {
+            self.mark_changed(screen);
+        }
+    }
+
+    pub fn get_canvas(&self) -> &[Pixel<R>] {
+        self.canvas
+    }
+
+    pub fn get_renderer(&mut self) -> &mut R {
+        &mut self.renderer
+    }
+
+    pub fn into_renderer(self) -> R {
+        self.renderer
+    }
+
+    fn screen_area(&self) -> ImageArea {
+        ImageArea {
+            xpos: 0,
+            ypos: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// the part of area that is on the canvas
+    fn clip(&self, area: ImageArea) -> Option<ImageArea> {
+        let x_end = area.xpos.saturating_add(area.width).min(self.width);
+        let y_end = area.ypos.saturating_add(area.height).min(self.height);
+
+        if area.xpos >= x_end || area.ypos >= y_end {
+            return None;
+        }
+        Some(ImageArea {
+            xpos: area.xpos,
+            ypos: area.ypos,
+            width: x_end - area.xpos,
+            height: y_end - area.ypos,
+        })
+    }
+
+    /// canvas index range of row y of the area
+    fn row_range(&self, area: ImageArea, y: u16) -> core::ops::Range<usize> {
+        let start = y as usize * self.width as usize + area.xpos as usize;
+        start..start + area.width as usize
+    }
+
+    fn mark_changed(&mut self, area: ImageArea) {
+        self.changed = Some(match self.changed {
+            Some(changed) => bounding_box(changed, area),
+            None => area,
+        });
+    }
+
+    fn fill(&mut self, area: ImageArea, color: Pixel<R>) {
+        let Some(area) = self.clip(area) else {
+            return;
+        };
+        for y in area.ypos..area.ypos + area.height {
+            let pixels = core::iter::repeat(Some(color)).take(area.width as usize);
+            self.update_row(area.xpos, y, pixels);
+        }
+    }
+
+    /// Puts the pixels into row y starting at xpos, None keeps the pixel of the canvas.
+    /// Only the span from the first to the last pixel that differs is marked as changed.
+    fn update_row(&mut self, xpos: u16, y: u16, pixels: impl Iterator<Item = Option<Pixel<R>>>) {
+        let start = y as usize * self.width as usize + xpos as usize;
+        let mut span: Option<(u16, u16)> = None;
+
+        for (x, (pixel, new)) in (xpos..).zip(self.canvas[start..].iter_mut().zip(pixels)) {
+            match new {
+                Some(new) if *pixel != new => {
+                    *pixel = new;
+                    span = Some(match span {
+                        Some((first, _)) => (first, x),
+                        None => (x, x),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((first, last)) = span {
+            self.mark_changed(ImageArea {
+                xpos: first,
+                ypos: y,
+                width: last - first + 1,
+                height: 1,
+            });
+        }
+    }
+}
+
+impl<'c, R> ImageRenderer for Compositor<'c, R>
+where
+    R: PixelRenderer,
+    Pixel<R>: PartialEq,
+{
+    type Format = R::Format;
+    type Error = R::Error;
+
+    fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<Self::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), R::Error> {
+        let Some(visible) = self.clip(area) else {
+            return Ok(());
+        };
+
+        for y in visible.ypos..visible.ypos + visible.height {
+            let start = (y - area.ypos) as usize * area.width as usize;
+            let row = &buffer[start..start + visible.width as usize];
+            let pixels = row.iter().map(|&index| match transparency_index {
+                Some(transparency_index) if index == transparency_index => None,
+                _ => Some(color_table[index as usize]),
+            });
+            self.update_row(visible.xpos, y, pixels);
+        }
+        Ok(())
+    }
+
+    /// sends the changed region of the canvas, full width regions in a single call
+    fn flush_frame(&mut self) -> Result<(), R::Error> {
+        if let Some(changed) = self.changed.take() {
+            if changed.width == self.width {
+                let start = changed.ypos as usize * self.width as usize;
+                let len = changed.width as usize * changed.height as usize;
+                self.renderer
+                    .write_pixels(changed, &self.canvas[start..start + len])?;
+            } else {
+                for y in changed.ypos..changed.ypos + changed.height {
+                    let row = ImageArea {
+                        ypos: y,
+                        height: 1,
+                        ..changed
+                    };
+                    let range = self.row_range(changed, y);
+                    self.renderer.write_pixels(row, &self.canvas[range])?;
+                }
+            }
+        }
+        self.renderer.flush_frame()
+    }
+
+    fn save_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        let Some(area) = self.clip(area) else {
+            return Ok(());
+        };
+        for y in area.ypos..area.ypos + area.height {
+            let range = self.row_range(area, y);
+            if let Some(saved) = self.saved.as_deref_mut() {
+                if let Some(saved) = saved.get_mut(range.clone()) {
+                    saved.copy_from_slice(&self.canvas[range]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clear_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        self.fill(area, self.clear_color);
+        Ok(())
+    }
+
+    fn fill_area(&mut self, area: ImageArea, color: Pixel<R>) -> Result<(), R::Error> {
+        self.fill(area, color);
+        Ok(())
+    }
+
+    fn restore_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        let Some(area) = self.clip(area) else {
+            return Ok(());
+        };
+        let Some(saved) = self.saved.take() else {
+            return Ok(());
+        };
+        for y in area.ypos..area.ypos + area.height {
+            let range = self.row_range(area, y);
+            if let Some(row) = saved.get(range) {
+                self.update_row(area.xpos, y, row.iter().copied().map(Some));
+            }
+        }
+        self.saved = Some(saved);
+        Ok(())
+    }
+}
+
+/// smallest area that contains a and b
+fn bounding_box(a: ImageArea, b: ImageArea) -> ImageArea {
+    let xpos = a.xpos.min(b.xpos);
+    let ypos = a.ypos.min(b.ypos);
+    let x_end = (a.xpos + a.width).max(b.xpos + b.width);
+    let y_end = (a.ypos + a.height).max(b.ypos + b.height);
+
+    ImageArea {
+        xpos,
+        ypos,
+        width: x_end - xpos,
+        height: y_end - ypos,
+    }
+}
@@ -0,0 +1,179 @@
+use crate::gif_decoder::Rewindable;
+use crate::gif_error::Error;
+
+/// Source of the bytes of a GIF file.
+/// Implemented for all iterators over bytes. Sources that have the data in memory
+/// can also hand out whole fields and sub-blocks at once, see SliceSource.
+/// Returns Error::FileEnded at the end of the data and e.g. Error::ReadError when the
+/// underlying storage fails.
+pub trait DataSource {
+    fn next_byte(&mut self) -> Result<u8, Error>;
+
+    /// reads a fixed size field of the file
+    fn next_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut bytes = [0u8; N];
+        for byte in bytes.iter_mut() {
+            *byte = self.next_byte()?;
+        }
+        Ok(bytes)
+    }
+
+    /// Passes the next len bytes to f, which can be called with several smaller slices.
+    /// Used for the data sub-blocks, errors of f are passed through.
+    fn take_bytes<F, E>(&mut self, len: usize, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+        E: From<Error>,
+    {
+        for _ in 0..len {
+            f(&[self.next_byte()?])?;
+        }
+        Ok(())
+    }
+}
+
+/// Data sources that can jump to any byte offset of the file, see GifDecoder::seek_to_frame()
+pub trait Seekable {
+    /// offset of the next byte in the file
+    fn position(&self) -> usize;
+
+    fn seek(&mut self, offset: usize) -> Result<(), Error>;
+}
+
+impl<I> DataSource for I
+where
+    I: Iterator<Item = u8>,
+{
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        self.next().ok_or(Error::FileEnded)
+    }
+}
+
+/// Data source for files that are completely in memory, e.g. in memory-mapped flash.
+/// Fields and sub-blocks are read directly from the slice.
+pub struct SliceSource<'d> {
+    data: &'d [u8],
+    position: usize,
+}
+
+impl<'d> SliceSource<'d> {
+    pub fn new(data: &'d [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+}
+
+impl<'d> DataSource for SliceSource<'d> {
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        let byte = *self.data.get(self.position).ok_or(Error::FileEnded)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn next_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + N)
+            .ok_or(Error::FileEnded)?;
+        self.position += N;
+
+        let mut field = [0u8; N];
+        field.copy_from_slice(bytes);
+        Ok(field)
+    }
+
+    fn take_bytes<F, E>(&mut self, len: usize, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+        E: From<Error>,
+    {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .ok_or(Error::FileEnded)?;
+        self.position += len;
+
+        f(bytes)
+    }
+}
+
+impl<'d> Rewindable for SliceSource<'d> {
+    fn rewind(&mut self) -> Result<(), Error> {
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<'d> Seekable for SliceSource<'d> {
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn seek(&mut self, offset: usize) -> Result<(), Error> {
+        if offset > self.data.len() {
+            return Err(Error::SeekError);
+        }
+        self.position = offset;
+        Ok(())
+    }
+}
+
+/// Wraps the data source of a decoder and counts the bytes taken from it,
+/// so that errors can report their offset in the file
+pub(crate) struct CountingSource<DS> {
+    pub(crate) source: DS,
+    /// offset of the next byte in the file
+    pub(crate) offset: usize,
+}
+
+impl<DS> CountingSource<DS> {
+    pub(crate) fn new(source: DS) -> Self {
+        Self { source, offset: 0 }
+    }
+}
+
+impl<DS: DataSource> DataSource for CountingSource<DS> {
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        let byte = self.source.next_byte()?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn next_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let bytes = self.source.next_bytes()?;
+        self.offset += N;
+        Ok(bytes)
+    }
+
+    fn take_bytes<F, E>(&mut self, len: usize, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+        E: From<Error>,
+    {
+        let offset = &mut self.offset;
+        self.source.take_bytes(len, |bytes| {
+            f(bytes)?;
+            *offset += bytes.len();
+            Ok(())
+        })
+    }
+}
+
+impl<DS: Rewindable> Rewindable for CountingSource<DS> {
+    fn rewind(&mut self) -> Result<(), Error> {
+        self.source.rewind()?;
+        self.offset = 0;
+        Ok(())
+    }
+}
+
+impl<DS: Seekable> Seekable for CountingSource<DS> {
+    fn position(&self) -> usize {
+        self.source.position()
+    }
+
+    fn seek(&mut self, offset: usize) -> Result<(), Error> {
+        self.source.seek(offset)?;
+        self.offset = offset;
+        Ok(())
+    }
+}
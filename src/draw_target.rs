@@ -0,0 +1,233 @@
+use crate::data_source::DataSource;
+use crate::frame_decoder::ImageArea;
+use crate::gif_decoder::GifDecoder;
+use crate::gif_error::{DecodeError, Error, Failure};
+use crate::pixel_format::{ColorTable, PixelFormat};
+use crate::renderer::{ImageRenderer, PixelRenderer};
+use core::cell::{Cell, RefCell};
+use core::marker::PhantomData;
+use embedded_graphics::draw_target::{DrawTarget, DrawTargetExt};
+use embedded_graphics::geometry::{OriginDimensions, Point, Size};
+use embedded_graphics::image::ImageDrawable;
+use embedded_graphics::pixelcolor::{PixelColor, Rgb888};
+use embedded_graphics::primitives::{PointsIter, Rectangle};
+use embedded_graphics::Pixel;
+
+/// Pixel format of the embedded-graphics color C, e.g. Rgb565, Gray8 or BinaryColor.
+/// The colors of the color tables are converted from Rgb888 by embedded-graphics.
+pub struct PixelColorFormat<C>(PhantomData<C>);
+
+impl<C> PixelFormat for PixelColorFormat<C>
+where
+    C: PixelColor + From<Rgb888> + Default,
+{
+    type Pixel = C;
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self::Pixel {
+        C::from(Rgb888::new(r, g, b))
+    }
+}
+
+/// Renderer that draws onto any embedded-graphics DrawTarget, in the color of the target.
+/// Each write_area() burst is pushed with a single fill_contiguous() call,
+/// bursts with transparent pixels are drawn pixel by pixel with draw_iter() instead.
+/// Errors of the draw target are returned as Failure::Render in DecodeError.
+pub struct DrawTargetRenderer<'t, D: DrawTarget> {
+    target: &'t mut D,
+}
+
+impl<'t, D> DrawTargetRenderer<'t, D>
+where
+    D: DrawTarget,
+    D::Color: From<Rgb888> + Default,
+{
+    pub fn new(target: &'t mut D) -> Self {
+        Self { target }
+    }
+}
+
+impl<'t, D> ImageRenderer for DrawTargetRenderer<'t, D>
+where
+    D: DrawTarget,
+    D::Color: From<Rgb888> + Default,
+{
+    type Format = PixelColorFormat<D::Color>;
+    type Error = D::Error;
+
+    fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<Self::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), D::Error> {
+        let rectangle = rectangle_from_area(area);
+        let pixels = &buffer[..area.width as usize * area.height as usize];
+        let color = |index: u8| color_table[index as usize];
+
+        match transparency_index {
+            None => self
+                .target
+                .fill_contiguous(&rectangle, pixels.iter().map(|&index| color(index))),
+            Some(transparency_index) => self.target.draw_iter(
+                rectangle
+                    .points()
+                    .zip(pixels)
+                    .filter(|(_, &index)| index != transparency_index)
+                    .map(|(point, &index)| Pixel(point, color(index))),
+            ),
+        }
+    }
+
+    fn flush_frame(&mut self) -> Result<(), D::Error> {
+        Ok(())
+    }
+
+    fn fill_area(&mut self, area: ImageArea, color: D::Color) -> Result<(), D::Error> {
+        self.target.fill_solid(&rectangle_from_area(area), color)
+    }
+}
+
+/// draws the changed region of a Compositor
+impl<'t, D> PixelRenderer for DrawTargetRenderer<'t, D>
+where
+    D: DrawTarget,
+    D::Color: From<Rgb888> + Default,
+{
+    type Format = PixelColorFormat<D::Color>;
+    type Error = D::Error;
+
+    fn write_pixels(&mut self, area: ImageArea, pixels: &[D::Color]) -> Result<(), D::Error> {
+        self.target
+            .fill_contiguous(&rectangle_from_area(area), pixels.iter().copied())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), D::Error> {
+        Ok(())
+    }
+}
+
+fn rectangle_from_area(area: ImageArea) -> Rectangle {
+    Rectangle::new(
+        Point::new(area.xpos as i32, area.ypos as i32),
+        Size::new(area.width as u32, area.height as u32),
+    )
+}
+
+/// Adapter to draw the current frame with the embedded-graphics Image API:
+/// `Image::new(&decoder.frame_image(), offset).draw(&mut display)`
+///
+/// The image data is decoded while drawing, so a frame can only be drawn once,
+/// after parse_frame_metadata() has been called. The size of the image is the
+/// logical screen size of the GIF file.
+/// Errors of the draw target are returned by draw(), other decoding errors are
+/// kept and can be retrieved with take_error().
+pub struct GifFrameImage<'d, 'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+where
+    R: ImageRenderer,
+{
+    decoder: RefCell<&'d mut GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>>,
+    size: Size,
+    drawn: Cell<bool>,
+    error: Cell<Option<DecodeError>>,
+}
+
+impl<'d, 'a, DS, R, C, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    GifFrameImage<'d, 'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource,
+    R: ImageRenderer<Format = PixelColorFormat<C>>,
+    C: PixelColor + From<Rgb888> + Default,
+{
+    /// returns the decoding error of the last draw() call
+    pub fn take_error(&self) -> Option<DecodeError> {
+        self.error.take()
+    }
+
+    fn decode_into<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        if self.drawn.replace(true) {
+            let error = self.decoder.borrow().locate(Error::DecoderAlreadyFinished);
+            self.error.set(Some(error.without_render_error()));
+            return Ok(());
+        }
+
+        let mut renderer = DrawTargetRenderer::new(target);
+        let result = self
+            .decoder
+            .borrow_mut()
+            .decode_frame_image_into(&mut renderer);
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(DecodeError {
+                failure: Failure::Render(target_error),
+                ..
+            }) => Err(target_error),
+            Err(error) => {
+                self.error.set(Some(error.without_render_error()));
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'d, 'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize> OriginDimensions
+    for GifFrameImage<'d, 'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    R: ImageRenderer,
+{
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl<'d, 'a, DS, R, C, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize> ImageDrawable
+    for GifFrameImage<'d, 'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource,
+    R: ImageRenderer<Format = PixelColorFormat<C>>,
+    C: PixelColor + From<Rgb888> + Default,
+{
+    type Color = C;
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        self.decode_into(target)
+    }
+
+    fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let mut translated = target.translated(-area.top_left);
+        self.decode_into(&mut translated.clipped(area))
+    }
+}
+
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource,
+    R: ImageRenderer,
+{
+    /// Wraps the current frame for drawing with the embedded-graphics Image API,
+    /// see GifFrameImage.
+    pub fn frame_image(&mut self) -> GifFrameImage<'_, 'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN> {
+        let size = match self.get_gif_metadata() {
+            Some(metadata) => Size::new(metadata.width as u32, metadata.height as u32),
+            None => Size::zero(),
+        };
+
+        GifFrameImage {
+            decoder: RefCell::new(self),
+            size,
+            drawn: Cell::new(false),
+            error: Cell::new(None),
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use crate::gif_decoder::{OUT_BUF_LEN, REVERSE_BUF_LEN};
-use crate::gif_error::Error;
+use crate::data_source::DataSource;
+use crate::gif_error::{Error, Failure};
+use crate::pixel_format::ColorTable;
 use crate::renderer::ImageRenderer;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct ImageArea {
     pub xpos: u16,
     pub ypos: u16,
@@ -10,8 +11,36 @@ pub struct ImageArea {
     pub height: u16,
 }
 
+/// What happens to the area of a frame after it has been displayed.
+/// See GIF 89a spec section 23.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisposalMethod {
+    /// no disposal specified, treated like DoNotDispose
+    #[default]
+    Unspecified,
+    /// leave the frame in place
+    DoNotDispose,
+    /// clear the frame area to the background
+    RestoreToBackground,
+    /// restore the frame area to what it was before the frame was drawn
+    RestoreToPrevious,
+}
+
+impl DisposalMethod {
+    /// values 4-7 are reserved by the spec and treated as unspecified
+    pub(crate) fn from_packed_fields(packed_fields: u8) -> Self {
+        match (packed_fields >> 2) & 0b111 {
+            1 => DisposalMethod::DoNotDispose,
+            2 => DisposalMethod::RestoreToBackground,
+            3 => DisposalMethod::RestoreToPrevious,
+            _ => DisposalMethod::Unspecified,
+        }
+    }
+}
+
 pub struct GraphicsControlExtension {
     pub millis_delay: u32,
+    pub disposal_method: DisposalMethod,
     pub has_transparency: bool,
     pub transparency_index: u8,
 }
@@ -20,29 +49,74 @@ pub struct GifFrameMetadata {
     pub frame_area: ImageArea,
     pub local_color_table_size: usize,
     pub has_local_color_table: bool,
+    pub interlaced: bool,
     pub extension: Option<GraphicsControlExtension>,
 }
 
+/// Metadata of a frame as returned by GifDecoder::next_frame(),
+/// with the values of the graphics control extension resolved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub frame_area: ImageArea,
+    /// 0 if the frame has no graphics control extension
+    pub delay_ms: u32,
+    pub disposal_method: DisposalMethod,
+    pub transparency_index: Option<u8>,
+    pub interlaced: bool,
+    pub has_local_color_table: bool,
+}
+
+impl From<&GifFrameMetadata> for FrameInfo {
+    fn from(metadata: &GifFrameMetadata) -> Self {
+        let (delay_ms, disposal_method, transparency_index) = match &metadata.extension {
+            Some(extension) => (
+                extension.millis_delay,
+                extension.disposal_method,
+                match extension.has_transparency {
+                    true => Some(extension.transparency_index),
+                    false => None,
+                },
+            ),
+            None => (0, DisposalMethod::Unspecified, None),
+        };
+
+        FrameInfo {
+            frame_area: metadata.frame_area,
+            delay_ms,
+            disposal_method,
+            transparency_index,
+            interlaced: metadata.interlaced,
+            has_local_color_table: metadata.has_local_color_table,
+        }
+    }
+}
+
 // TODO is speedup due to aligned access significant enough to justify this much padding?
 #[derive(Default, Clone, Copy)]
+#[allow(clippy::repr_packed_without_abi)] // the layout is only used within the crate
 #[repr(packed(4))] // TODO does this work as intended?
 pub struct LzwEntry {
     first: u16,
     last: u8,
 }
 
-/// Decodes a single frame of a GIF file using LZW compression
-// a 2-12 bit input token is reffered to as a symbol,
-// an lzw table entry containing a pair of symbols is caled an entry
-pub struct FrameDecoder<'a, DS, R> {
+/// Progress of the end of the image data
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Decoding,
+    /// the stop code has been read, the rest of the output buffer needs to be written
+    StopCode,
+    /// all rows have been written, the renderer needs to be flushed
+    Flush,
+    /// everything after the stop code only pads the last sub-block
+    Finished,
+}
+
+/// Scalar state of the LZW decompression of one frame. It does not borrow anything,
+/// so decoding can be suspended between any two bytes and resumed later.
+#[derive(Clone, Copy)]
+pub(crate) struct LzwState {
     // initial state
-    data_source: &'a mut DS,
-    frame_metadata: &'a GifFrameMetadata,
-    color_table: &'a mut [u16; 256],
-    lzw_table: &'a mut [LzwEntry; 4096],
-    reverse_buffer: &'a mut [u8],
-    output_buffer: &'a mut [u8],
-    renderer: &'a mut R,
     initial_symbol_size: u8,
     clear_code: u16,
     stop_code: u16,
@@ -55,25 +129,18 @@ pub struct FrameDecoder<'a, DS, R> {
     bit_buffer: u32,
     bit_count: u8,
     last_symbol: Option<u16>,
+    /// pixels of the last entry chain that are still in the reverse buffer
+    reverse_index: usize,
     output_line: u16,
     output_index: usize,
-    finished: bool,
+    stage: Stage,
 }
 
-impl<'a, DS, R> FrameDecoder<'a, DS, R>
-where
-    DS: Iterator<Item = u8>,
-    R: ImageRenderer,
-{
+impl LzwState {
     pub(crate) fn new(
-        data_source: &'a mut DS,
-        frame_metadata: &'a GifFrameMetadata,
-        color_table: &'a mut [u16; 256],
-        lzw_table: &'a mut [LzwEntry; 4096],
-        reverse_buffer: &'a mut [u8],
-        output_buffer: &'a mut [u8],
-        renderer: &'a mut R,
+        frame_metadata: &GifFrameMetadata,
         initial_lzw_size: u8,
+        output_buffer_len: usize,
     ) -> Self {
         let clear_code = 1 << initial_lzw_size;
 
@@ -86,16 +153,15 @@ where
             _ => None,
         };
 
-        let output_section_height = (OUT_BUF_LEN / frame_metadata.frame_area.width as usize) as u16;
+        // interlaced rows are not contiguous, so they are emitted one at a time.
+        // Narrow frames in large buffers fit more rows than an area can hold
+        let output_section_height = match frame_metadata.interlaced {
+            true => 1,
+            false => (output_buffer_len / frame_metadata.frame_area.width.max(1) as usize)
+                .clamp(1, u16::MAX as usize) as u16,
+        };
 
         Self {
-            data_source,
-            frame_metadata,
-            color_table,
-            lzw_table,
-            reverse_buffer,
-            output_buffer,
-            renderer,
             initial_symbol_size: initial_lzw_size + 1,
             clear_code,
             stop_code: clear_code + 1,
@@ -107,139 +173,212 @@ where
             bit_buffer: 0,
             bit_count: 0,
             last_symbol: None,
+            reverse_index: 0,
             output_line: 0,
             output_index: 0,
-            finished: false,
+            stage: Stage::Decoding,
+        }
+    }
+}
+
+/// What the LZW decoder needs from its caller to continue
+pub(crate) enum LzwOutput {
+    /// all symbols of the input have been decoded, pass the next bytes
+    NeedInput,
+    /// the output buffer holds the pixels of this area and needs to be written
+    Rows(ImageArea),
+    /// all pixels of the frame have been output
+    Finished,
+}
+
+/// LZW decompression of a single frame, independent of the renderer.
+/// The caller passes the bytes to next_output() until they are used up,
+/// writing the output buffer whenever rows are ready.
+// a 2-12 bit input token is reffered to as a symbol,
+// an lzw table entry containing a pair of symbols is caled an entry
+pub(crate) struct LzwDecoder<'a, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize> {
+    state: &'a mut LzwState,
+    frame_metadata: &'a GifFrameMetadata,
+    lzw_table: &'a mut [LzwEntry; 4096],
+    reverse_buffer: &'a mut [u8; REVERSE_BUF_LEN],
+    output_buffer: &'a mut [u8; OUT_BUF_LEN],
+}
+
+impl<'a, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    LzwDecoder<'a, OUT_BUF_LEN, REVERSE_BUF_LEN>
+{
+    pub(crate) fn new(
+        state: &'a mut LzwState,
+        frame_metadata: &'a GifFrameMetadata,
+        lzw_table: &'a mut [LzwEntry; 4096],
+        reverse_buffer: &'a mut [u8; REVERSE_BUF_LEN],
+        output_buffer: &'a mut [u8; OUT_BUF_LEN],
+    ) -> Self {
+        Self {
+            state,
+            frame_metadata,
+            lzw_table,
+            reverse_buffer,
+            output_buffer,
         }
     }
 
-    fn next_byte(&mut self) -> Result<u8, Error> {
-        self.data_source.next().ok_or(Error::FileEnded)
+    pub(crate) fn output_buffer(&self) -> &[u8] {
+        self.output_buffer
     }
 
-    /// consumes and decoded all blocks of image data in input stream
-    pub(crate) fn decode_frame(&mut self) -> Result<(), Error> {
-        let mut block_size = self.next_byte()?;
+    pub(crate) fn transparency_index(&self) -> Option<u8> {
+        self.state.transparency_index
+    }
 
-        while block_size != 0 {
-            for _ in 0..block_size {
-                let data = self.next_byte()?;
-                self.process_byte(data)?;
-            }
-            block_size = self.next_byte()?;
+    /// moves as many bytes of the input into the bit buffer as fit
+    fn refill(&mut self, input: &mut &[u8]) {
+        while self.state.bit_count <= 24 {
+            let Some((&byte, rest)) = input.split_first() else {
+                return;
+            };
+            self.state.bit_buffer = self.state.bit_buffer >> 8 | (byte as u32) << 24;
+            self.state.bit_count += 8;
+            *input = rest;
         }
-        Ok(())
     }
 
-    /// takes a single byte from the file and extracts the varibale-width symbols
-    fn process_byte(&mut self, byte: u8) -> Result<(), Error> {
-        self.bit_buffer = self.bit_buffer >> 8 | (byte as u32) << 24;
-        self.bit_count += 8;
+    /// Decodes symbols until the output buffer is full or the input is used up.
+    /// The consumed bytes are removed from the input, the bytes left are still needed.
+    pub(crate) fn next_output(&mut self, input: &mut &[u8]) -> Result<LzwOutput, Error> {
+        // frames without width still need room for the pixels they send
+        let section_size = self.frame_metadata.frame_area.width.max(1) as usize
+            * self.state.output_section_height as usize;
+
+        loop {
+            if self.state.output_index >= section_size {
+                return Ok(LzwOutput::Rows(
+                    self.take_rows(self.state.output_section_height),
+                ));
+            }
+
+            // unwind reverse buffer
+            if self.state.reverse_index > 0 {
+                let space = section_size - self.state.output_index;
+                for _ in 0..self.state.reverse_index.min(space) {
+                    self.state.reverse_index -= 1;
+                    self.output_pixel(self.reverse_buffer[self.state.reverse_index]);
+                }
+                continue;
+            }
+
+            match self.state.stage {
+                Stage::Decoding => {}
+                Stage::StopCode => {
+                    // end of image. Write rest of data and flush renderer
+                    self.state.stage = Stage::Flush;
+                    let frame_height = self.frame_metadata.frame_area.height;
+
+                    if self.state.output_line < frame_height {
+                        let remaining_height = frame_height - self.state.output_line;
+                        let height = remaining_height.min(self.state.output_section_height);
+                        return Ok(LzwOutput::Rows(self.take_rows(height)));
+                    }
+                    continue;
+                }
+                Stage::Flush => {
+                    self.state.stage = Stage::Finished;
+                    return Ok(LzwOutput::Finished);
+                }
+                Stage::Finished => {
+                    // the bytes after the stop code only pad the last sub-block
+                    *input = &[];
+                    return Ok(LzwOutput::NeedInput);
+                }
+            }
 
-        while self.current_symbol_size <= self.bit_count {
-            let shift = 32 - self.bit_count;
-            let mask = ((1u32 << self.current_symbol_size) - 1) << shift;
-            let symbol = ((self.bit_buffer & mask) >> shift) as u16;
-            self.bit_count -= self.current_symbol_size;
+            if self.state.current_symbol_size > self.state.bit_count {
+                self.refill(input);
+                if self.state.current_symbol_size > self.state.bit_count {
+                    return Ok(LzwOutput::NeedInput);
+                }
+            }
+
+            // extract the varibale-width symbol
+            let shift = 32 - self.state.bit_count;
+            let mask = ((1u32 << self.state.current_symbol_size) - 1) << shift;
+            let symbol = ((self.state.bit_buffer & mask) >> shift) as u16;
+            self.state.bit_count -= self.state.current_symbol_size;
 
             self.process_symbol(symbol)?;
         }
-        Ok(())
     }
 
     /// decodes a single LZW input symbol
     /// see https://de.wikipedia.org/wiki/Lempel-Ziv-Welch-Algorithmus
     fn process_symbol(&mut self, symbol: u16) -> Result<(), Error> {
-        if self.finished {
-            return Err(Error::DecoderAlreadyFinished);
-        }
-
-        if symbol == self.clear_code {
-            return self.on_clear_code();
-        } else if symbol == self.stop_code {
-            return self.on_stop_code();
+        if symbol == self.state.clear_code {
+            self.on_clear_code();
+            return Ok(());
+        } else if symbol == self.state.stop_code {
+            self.state.stage = Stage::StopCode;
+            return Ok(());
         };
 
         // first iteration
-        if self.last_symbol == None {
-            self.last_symbol = Some(symbol);
+        if self.state.last_symbol.is_none() {
+            self.state.last_symbol = Some(symbol);
 
-            return self.process_pixel(symbol as u8);
+            self.output_pixel(symbol as u8);
+            return Ok(());
         }
 
-        if symbol > self.table_index + 1 {
-            return Err(Error::InvalidSymbol);
+        if symbol > self.state.table_index + 1 {
+            return Err(Error::InvalidSymbol(symbol));
         }
 
         // space in table
-        if self.table_index < 4096 - 1 {
+        if self.state.table_index < 4096 - 1 {
             // handle lzw special case
-            let current_symbol = if symbol <= self.table_index {
+            let current_symbol = if symbol <= self.state.table_index {
                 symbol
             } else {
-                self.last_symbol.unwrap()
+                self.state.last_symbol.unwrap()
             };
 
             let first_symbol = self.find_first_symbol_in_chain(current_symbol);
             let new_entry = LzwEntry {
-                first: self.last_symbol.unwrap(),
+                first: self.state.last_symbol.unwrap(),
                 last: first_symbol,
             };
 
-            self.table_index += 1;
-            self.lzw_table[self.table_index as usize] = new_entry;
+            self.state.table_index += 1;
+            self.lzw_table[self.state.table_index as usize] = new_entry;
 
             // check for new sybol size
-            if self.table_index + 1 == 1 << self.current_symbol_size {
-                if self.current_symbol_size < 12 {
-                    self.current_symbol_size += 1;
-                }
+            if self.state.table_index + 1 == 1 << self.state.current_symbol_size
+                && self.state.current_symbol_size < 12
+            {
+                self.state.current_symbol_size += 1;
             }
         }
 
         self.emit_entry_chain(symbol)?;
 
-        self.last_symbol = Some(symbol);
+        self.state.last_symbol = Some(symbol);
         Ok(())
     }
 
     /// resets the decoding tables to achieve higher compression ratios
-    fn on_clear_code(&mut self) -> Result<(), Error> {
+    fn on_clear_code(&mut self) {
         // reset table
-        self.current_symbol_size = self.initial_symbol_size;
-        self.table_index = self.stop_code;
+        self.state.current_symbol_size = self.state.initial_symbol_size;
+        self.state.table_index = self.state.stop_code;
 
         // The spec is not clear about this. I assume, the lastSymbol
         // should be refetched on a clear symbol. This seems to work
-        self.last_symbol = None;
-        Ok(())
+        self.state.last_symbol = None;
     }
 
-    /// end of image. Write rest of data and flush renderer
-    fn on_stop_code(&mut self) -> Result<(), Error> {
-        if self.output_line < self.frame_metadata.frame_area.height {
-            let remaining_height = self.frame_metadata.frame_area.height - self.output_line;
-            self.render_buffer(remaining_height)?;
-        }
-        self.finished = true;
-
-        self.renderer.flush_frame()?;
-        Ok(())
-    }
-
-    /// puts a pixel into the output buffer and renders it when full
-    /// TODO refactoring the pixel processing into a different module might be a good idea
-    fn process_pixel(&mut self, pixel: u8) -> Result<(), Error> {
-        self.output_buffer[self.output_index] = pixel;
-        self.output_index += 1;
-
-        let max_size =
-            self.frame_metadata.frame_area.width as usize * self.output_section_height as usize;
-
-        if self.output_index >= max_size {
-            self.render_buffer(self.output_section_height)?;
-        }
-        Ok(())
+    /// puts a pixel into the output buffer, which the caller made sure is not full
+    fn output_pixel(&mut self, pixel: u8) {
+        self.output_buffer[self.state.output_index] = pixel;
+        self.state.output_index += 1;
     }
 
     /// follows a chain of LZW table entries until it finds a literal
@@ -247,21 +386,23 @@ where
     fn find_first_symbol_in_chain(&mut self, start: u16) -> u8 {
         let mut current_symbol = start;
 
-        while current_symbol >= self.clear_code {
+        while current_symbol >= self.state.clear_code {
             current_symbol = self.lzw_table[current_symbol as usize].first;
         }
 
         current_symbol as u8
     }
 
-    /// reverses chain of LZW table entries and outputs them
+    /// Reverses chain of LZW table entries into the reverse buffer,
+    /// next_output() moves them into the output buffer
     fn emit_entry_chain(&mut self, start: u16) -> Result<(), Error> {
         let mut current_symbol = start;
         let mut reverse_index = 0;
 
         // shortcut for hot path
-        if start < self.clear_code {
-            return self.process_pixel(start as u8);
+        if start < self.state.clear_code {
+            self.output_pixel(start as u8);
+            return Ok(());
         }
 
         // follow chain
@@ -276,39 +417,117 @@ where
                 return Err(Error::ReverseBufferOverflow);
             }
 
-            if entry.first < self.clear_code {
+            if entry.first < self.state.clear_code {
                 self.reverse_buffer[reverse_index] = entry.first as u8;
                 reverse_index += 1;
                 break;
             }
         }
 
-        // unwind reverse buffer
-        while reverse_index > 0 {
-            reverse_index -= 1;
-            self.process_pixel(self.reverse_buffer[reverse_index])?;
-        }
+        self.state.reverse_index = reverse_index;
         Ok(())
     }
 
-    fn render_buffer(&mut self, height: u16) -> Result<(), Error> {
-        let output_area = ImageArea {
-            xpos: self.frame_metadata.frame_area.xpos,
-            ypos: self.frame_metadata.frame_area.ypos + self.output_line,
-            width: self.frame_metadata.frame_area.width,
-            height,
+    /// the area of the next rows in the output buffer, which is then free again
+    fn take_rows(&mut self, height: u16) -> ImageArea {
+        let frame_area = &self.frame_metadata.frame_area;
+
+        let row = match self.frame_metadata.interlaced {
+            true => interlaced_row(self.state.output_line, frame_area.height),
+            false => self.state.output_line,
         };
 
-        self.renderer.write_area(
-            output_area,
-            self.output_buffer,
-            self.color_table,
-            self.transparency_index,
-        )?;
+        self.state.output_index = 0;
+        self.state.output_line += height;
 
-        self.output_index = 0;
-        self.output_line += height;
+        ImageArea {
+            xpos: frame_area.xpos,
+            ypos: frame_area.ypos + row,
+            width: frame_area.width,
+            height,
+        }
+    }
+}
 
+/// Decodes a single frame of a GIF file using LZW compression
+/// and writes it to the renderer
+pub struct FrameDecoder<'a, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+where
+    R: ImageRenderer,
+{
+    decoder: LzwDecoder<'a, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+    color_table: &'a ColorTable<R::Format>,
+    renderer: &'a mut R,
+}
+
+impl<'a, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    FrameDecoder<'a, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    R: ImageRenderer,
+{
+    pub(crate) fn new(
+        decoder: LzwDecoder<'a, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+        color_table: &'a ColorTable<R::Format>,
+        renderer: &'a mut R,
+    ) -> Self {
+        Self {
+            decoder,
+            color_table,
+            renderer,
+        }
+    }
+
+    /// consumes and decoded all blocks of image data in input stream
+    pub(crate) fn decode_frame<DS>(&mut self, data_source: &mut DS) -> Result<(), Failure<R::Error>>
+    where
+        DS: DataSource,
+    {
+        let mut block_size = data_source.next_byte()?;
+
+        while block_size != 0 {
+            data_source.take_bytes(block_size as usize, |bytes| self.process_bytes(bytes))?;
+            block_size = data_source.next_byte()?;
+        }
         Ok(())
     }
+
+    /// takes the contents of an image data sub-block, or any part of it,
+    /// and renders everything it completes
+    pub(crate) fn process_bytes(&mut self, bytes: &[u8]) -> Result<(), Failure<R::Error>> {
+        let mut input = bytes;
+
+        loop {
+            match self.decoder.next_output(&mut input)? {
+                LzwOutput::NeedInput => return Ok(()),
+                LzwOutput::Rows(area) => self
+                    .renderer
+                    .write_area(
+                        area,
+                        self.decoder.output_buffer(),
+                        self.color_table,
+                        self.decoder.transparency_index(),
+                    )
+                    .map_err(Failure::Render)?,
+                LzwOutput::Finished => self.renderer.flush_frame().map_err(Failure::Render)?,
+            }
+        }
+    }
+}
+
+/// Maps the n-th transmitted row of an interlaced image to its row in the frame.
+/// Rows are sent in four passes, see GIF 89a spec appendix E.
+fn interlaced_row(n: u16, height: u16) -> u16 {
+    // (first row, row step) of each pass
+    const PASSES: [(u16, u16); 4] = [(0, 8), (4, 8), (2, 4), (1, 2)];
+
+    let mut n = n;
+    for (start, step) in PASSES {
+        let pass_rows = height.saturating_sub(start).div_ceil(step);
+
+        if n < pass_rows {
+            return start + n * step;
+        }
+        n -= pass_rows;
+    }
+    height // only reached with more rows than the frame has
 }
@@ -0,0 +1,142 @@
+use crate::data_source::{DataSource, Seekable};
+use crate::frame_decoder::{DisposalMethod, GraphicsControlExtension, ImageArea};
+use crate::gif_decoder::{
+    parse_block_introducer, parse_extension_label, parse_graphics_control_extension, parse_header,
+    parse_image_descriptor, parse_logical_screen_descriptor, Block, Extension, GifDecoder,
+    GifFileMetadata,
+};
+use crate::gif_error::{DecodeError, Error};
+use crate::renderer::ImageRenderer;
+
+/// Position and metadata of a frame, recorded by GifDecoder::build_frame_index()
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameIndexEntry {
+    /// byte offset of the first block of the frame, including its extensions
+    pub offset: usize,
+    pub delay_ms: u32,
+    pub disposal_method: DisposalMethod,
+    pub frame_area: ImageArea,
+    pub interlaced: bool,
+    pub has_local_color_table: bool,
+}
+
+// random access to frames, needs a seekable data source
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource + Seekable,
+    R: ImageRenderer,
+{
+    /// Walks the block structure of the whole file and records the frames into index.
+    /// The image data is skipped without decoding it and the renderer is not called.
+    /// Returns the number of frames in the file, only the first index.len() are recorded.
+    /// Afterwards the data source is back at the position it had before.
+    pub fn build_frame_index(
+        &mut self,
+        index: &mut [FrameIndexEntry],
+    ) -> Result<usize, DecodeError<R::Error>> {
+        let position = self.data_source.position();
+        let mut frame_count = 0;
+        let result = self.scan_frames(index, &mut frame_count).map_err(|error| {
+            DecodeError::new(error.into(), self.data_source.offset, Some(frame_count))
+        });
+
+        self.data_source
+            .seek(position)
+            .map_err(|error| self.locate(error))?;
+        result.map(|()| frame_count)
+    }
+
+    /// counts the frames in frame_count, so that errors can report the frame
+    fn scan_frames(
+        &mut self,
+        index: &mut [FrameIndexEntry],
+        frame_count: &mut usize,
+    ) -> Result<(), Error> {
+        self.data_source.seek(0)?;
+
+        let version = parse_header(&self.next_bytes()?)?;
+        let metadata: GifFileMetadata<()> =
+            parse_logical_screen_descriptor(version, &self.next_bytes()?);
+        if metadata.has_global_color_table {
+            self.skip_bytes(3 * metadata.global_color_table_size)?;
+        }
+
+        let mut offset = self.data_source.position();
+        let mut extension: Option<GraphicsControlExtension> = None;
+
+        loop {
+            match parse_block_introducer(self.next_byte()?)? {
+                Block::ImageDescriptor => {
+                    let frame = parse_image_descriptor(&self.next_bytes()?, extension.take());
+                    if frame.has_local_color_table {
+                        self.skip_bytes(3 * frame.local_color_table_size)?;
+                    }
+                    self.skip_image_data()?;
+
+                    if let Some(entry) = index.get_mut(*frame_count) {
+                        let (delay_ms, disposal_method) = match frame.extension {
+                            Some(extension) => (extension.millis_delay, extension.disposal_method),
+                            None => (0, DisposalMethod::Unspecified),
+                        };
+                        *entry = FrameIndexEntry {
+                            offset,
+                            delay_ms,
+                            disposal_method,
+                            frame_area: frame.frame_area,
+                            interlaced: frame.interlaced,
+                            has_local_color_table: frame.has_local_color_table,
+                        };
+                    }
+                    *frame_count += 1;
+                    offset = self.data_source.position();
+                }
+                Block::Extension => {
+                    let [extension_label, block_size] = self.next_bytes()?;
+
+                    match parse_extension_label(extension_label, block_size) {
+                        Extension::GraphicsControl => {
+                            extension =
+                                Some(parse_graphics_control_extension(&self.next_bytes()?)?);
+                        }
+                        // the loop count is not needed for the index
+                        Extension::Application | Extension::Skipped => {
+                            self.skip_sub_blocks(block_size)?
+                        }
+                    }
+                }
+                Block::Trailer => return Ok(()),
+            }
+        }
+    }
+
+    fn skip_bytes(&mut self, len: usize) -> Result<(), Error> {
+        self.data_source.take_bytes(len, |_| Ok(()))
+    }
+
+    /// Continues at frame n of an index built by build_frame_index(),
+    /// parse_frame_metadata() then parses this frame. parse_gif_metadata() must have been
+    /// called before, as the global color table is needed.
+    ///
+    /// In compositing mode the disposal of the frame shown before is dropped, frame n is
+    /// drawn over what is on the screen. For a correct picture either seek to a frame
+    /// that covers the whole screen or decode the frames following the previous one.
+    pub fn seek_to_frame(
+        &mut self,
+        index: &[FrameIndexEntry],
+        n: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let entry = index
+            .get(n)
+            .ok_or_else(|| self.locate(Error::FrameNotFound))?;
+
+        self.data_source
+            .seek(entry.offset)
+            .map_err(|error| self.locate(error))?;
+        self.state.pending_disposal = None;
+        self.state.frame = n.checked_sub(1);
+        self.image_pending = false;
+        self.frames_ended = false;
+        Ok(())
+    }
+}
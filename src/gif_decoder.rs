@@ -1,25 +1,60 @@
+use crate::data_source::{CountingSource, DataSource};
 use crate::frame_decoder::{
-    FrameDecoder, GifFrameMetadata, GraphicsControlExtension, ImageArea, LzwEntry,
+    DisposalMethod, FrameDecoder, FrameInfo, GifFrameMetadata, GraphicsControlExtension, ImageArea,
+    LzwDecoder, LzwEntry, LzwState,
 };
-use crate::renderer::ImageRenderer;
-use crate::{gif_error::Error, util::color565_from_rgb};
-use core::{str::from_utf8, usize};
-
-pub const MAX_SIZE: u16 = 360;
-pub const REVERSE_BUF_LEN: usize = 512; // depends on MaxSize
-pub const OUT_BUF_LEN: usize = 240 * 20; // 20 lines
+use crate::gif_error::{DecodeError, Error, Failure};
+use crate::pixel_format::{ColorTable, PixelFormat};
+use crate::renderer::{ImageRenderer, RenderAction};
+use core::str::from_utf8;
 
 pub trait Rewindable {
     fn rewind(&mut self) -> Result<(), Error>;
 }
 
+/// Version of the GIF specification given in the file header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GifVersion {
+    Gif87a,
+    Gif89a,
+}
+
+/// How often an animation is played, from the NETSCAPE2.0 application extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopCount {
+    /// no looping extension, the animation is played once
+    Once,
+    /// the animation is repeated n times after it has been played once
+    Repeat(u16),
+    Infinite,
+}
+
+impl LoopCount {
+    /// whether the animation should be played again after completed_plays runs
+    pub fn should_repeat(&self, completed_plays: u32) -> bool {
+        match self {
+            LoopCount::Once => false,
+            LoopCount::Repeat(n) => completed_plays <= *n as u32,
+            LoopCount::Infinite => true,
+        }
+    }
+}
+
+/// P is the pixel type of the renderers format
 #[derive(Clone)]
-pub struct GifFileMetadata {
-    width: u16,
-    height: u16,
-    global_color_table_size: usize,
-    // background_color_index: u8, // TODO implement
-    has_global_color_table: bool,
+pub struct GifFileMetadata<P> {
+    pub version: GifVersion,
+    pub width: u16,
+    pub height: u16,
+    pub global_color_table_size: usize,
+    pub has_global_color_table: bool,
+    pub background_color_index: u8,
+    /// background color from the global color table, None if there is no global color table
+    /// or the index is outside of it
+    pub background_color: Option<P>,
+    /// only known after the first call to parse_frame_metadata(),
+    /// as the looping extension comes after the global color table
+    pub loop_count: LoopCount,
 }
 
 /// Streaming GIF Decoder.
@@ -27,288 +62,720 @@ pub struct GifFileMetadata {
 /// emits the resulting image in bursts of lines.
 /// Works completely allocationless, needs about 20kiB for the decoding tables.
 ///
+/// The sizes of the output and reverse buffers are chosen by the caller:
+/// OUT_BUF_LEN limits the maximum image width, as the buffer must hold at least one line.
+/// Bigger buffers allow rendering more lines per write_area() call.
+/// REVERSE_BUF_LEN limits the length of LZW chains. Chains can not be longer than the
+/// number of pixels in a frame, in practice 512 is enough for images up to 360x360.
+///
 /// Usage: Construct with a data source and a renderer. Call parse_gif_metadata().
-/// Then for each frame call parse_frame_metadata() followed by decode_frame_image().
-pub struct GifDecoder<'a, DS, R> {
-    data_source: DS,
-    file_metadata: Option<GifFileMetadata>,
-    current_frame_metadata: Option<GifFrameMetadata>,
+/// Then for each frame call parse_frame_metadata() followed by decode_frame_image(),
+/// or iterate the frames with next_frame().
+/// TypedGifDecoder wraps the decoder so that this order is checked at compile time.
+///
+/// By default every frame is simply drawn over the last one. With set_compositing(true)
+/// the decoder also honours the frame disposal methods by calling the renderers
+/// save_area(), clear_area() and restore_area() before the next frame is drawn.
+/// With set_fill_background(true) the logical screen is filled with the background color
+/// before the first frame and RestoreToBackground frames are disposed with fill_area().
+pub struct GifDecoder<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+where
+    R: ImageRenderer,
+{
+    pub(crate) data_source: CountingSource<DS>,
     renderer: &'a mut R,
-    global_color_table: &'a mut [u16; 256],
-    current_local_color_table: &'a mut [u16; 256],
+    pub(crate) state: DecoderState<'a, R::Format, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+    /// the image data of the frame returned by next_frame() has not been consumed yet
+    pub(crate) image_pending: bool,
+    /// next_frame() has reached the trailer or returned an error
+    pub(crate) frames_ended: bool,
+}
+
+/// Everything besides the data source and the renderer,
+/// so that frames can also be decoded into a different renderer.
+/// Also shared with the PushDecoder.
+pub(crate) struct DecoderState<
+    'a,
+    F: PixelFormat,
+    const OUT_BUF_LEN: usize,
+    const REVERSE_BUF_LEN: usize,
+> {
+    pub(crate) file_metadata: Option<GifFileMetadata<F::Pixel>>,
+    pub(crate) current_frame_metadata: Option<GifFrameMetadata>,
+    pub(crate) compositing: bool,
+    pub(crate) fill_background: bool,
+    pub(crate) pending_disposal: Option<(DisposalMethod, ImageArea)>,
+    /// index of the frame that is parsed or decoded, reported with errors
+    pub(crate) frame: Option<usize>,
+    pub(crate) global_color_table: &'a mut ColorTable<F>,
+    pub(crate) current_local_color_table: &'a mut ColorTable<F>,
     lzw_table: &'a mut [LzwEntry; 4096],
     reverse_buffer: &'a mut [u8; REVERSE_BUF_LEN],
     output_buffer: &'a mut [u8; OUT_BUF_LEN],
 }
 
-// TODO the proper way to implement this would be with seperate typestes
-// but that seems overkill fo now, because it is tricky to do allocationless
-impl<'a, DS, R> GifDecoder<'a, DS, R>
+// the methods are not checked for the order they are called in, decoding an image before
+// its frame metadata returns Error::MissingFrameMetadata. TypedGifDecoder enforces the order.
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
 where
-    DS: Iterator<Item = u8>,
+    DS: DataSource,
     R: ImageRenderer,
 {
     /// buffers need to be passed in from outside so that this object still fits on the stack
     pub fn new(
         data_source: DS,
         renderer: &'a mut R,
-        buf_a: &'a mut [u16; 256],
-        buf_b: &'a mut [u16; 256],
+        buf_a: &'a mut ColorTable<R::Format>,
+        buf_b: &'a mut ColorTable<R::Format>,
         buf_c: &'a mut [LzwEntry; 4096],
         buf_d: &'a mut [u8; REVERSE_BUF_LEN],
         buf_e: &'a mut [u8; OUT_BUF_LEN],
     ) -> Self {
         GifDecoder {
-            data_source,
-            file_metadata: None,
-            current_frame_metadata: None,
+            data_source: CountingSource::new(data_source),
             renderer,
-            global_color_table: buf_a,
-            current_local_color_table: buf_b,
-            lzw_table: buf_c,
-            reverse_buffer: buf_d,
-            output_buffer: buf_e,
+            state: DecoderState::new(buf_a, buf_b, buf_c, buf_d, buf_e),
+            image_pending: false,
+            frames_ended: false,
         }
     }
 
-    fn next_byte(&mut self) -> Result<u8, Error> {
-        self.data_source.next().ok_or(Error::FileEnded)
+    /// Enables honouring the disposal methods of frames, see ImageRenderer::clear_area().
+    /// Renderers without a frame buffer of their own can be wrapped in a Compositor.
+    pub fn set_compositing(&mut self, enabled: bool) {
+        self.state.compositing = enabled;
+        self.state.pending_disposal = None;
     }
 
-    fn next_short(&mut self) -> Result<u16, Error> {
-        let bytes: [u8; 2] = self.data_source.next_chunk().or(Err(Error::FileEnded))?;
-        Ok(u16::from_le_bytes(bytes))
+    /// Enables filling with the background color of the file, see ImageRenderer::fill_area().
+    /// Needs to be set before parse_gif_metadata() is called.
+    pub fn set_fill_background(&mut self, enabled: bool) {
+        self.state.fill_background = enabled;
     }
 
-    // === metadata ===
-
-    /// verify that the magic number and version of the gif file are correct
-    fn validate_header(&mut self) -> Result<(), Error> {
-        let header: [u8; 6] = self.data_source.next_chunk().or(Err(Error::FileEnded))?;
-
-        match from_utf8(&header) {
-            Ok("GIF89a") => Ok(()),
-            _ => Err(Error::WrongFiletype),
-        }
+    pub(crate) fn next_byte(&mut self) -> Result<u8, Error> {
+        self.data_source.next_byte()
     }
 
-    /// see GIF 89a spec section 18. Parses LogicalScreenDescriptor into gifMetadata
-    fn parse_logical_screen_descriptor(&mut self) -> Result<GifFileMetadata, Error> {
-        let width = self.next_short()?;
-        let height = self.next_short()?;
-        let packed_fields = self.next_byte()?;
-        let _background_color_index = self.next_byte()?;
-        self.next_byte()?; // ignore aspect ratio
-
-        let has_global_color_table = (packed_fields & 1 << 7) != 0;
-        let table_bits = (packed_fields & 0b00000111) + 1;
-        let global_color_table_size = 1 << table_bits;
-
-        Ok(GifFileMetadata {
-            width,
-            height,
-            // background_color_index,
-            has_global_color_table,
-            global_color_table_size,
-        })
+    pub(crate) fn next_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        self.data_source.next_bytes()
     }
 
-    fn parse_global_color_table(&mut self, size: usize) -> Result<(), Error> {
-        for i in 0..size {
-            let r = self.next_byte()?;
-            let g = self.next_byte()?;
-            let b = self.next_byte()?;
+    // === metadata ===
 
-            self.global_color_table[i as usize] = color565_from_rgb(r, g, b);
+    fn parse_color_table(&mut self, size: usize, local: bool) -> Result<(), Error> {
+        for i in 0..size {
+            let [r, g, b] = self.next_bytes()?;
+            self.state.set_color(local, i, r, g, b);
         }
         Ok(())
     }
 
-    fn parse_local_color_table(&mut self, size: usize) -> Result<(), Error> {
-        for i in 0..size {
-            let r = self.next_byte()?;
-            let g = self.next_byte()?;
-            let b = self.next_byte()?;
+    /// Parses and consumes the initial metadata section of a GIF file
+    pub fn parse_gif_metadata(&mut self) -> Result<(), DecodeError<R::Error>> {
+        self.image_pending = false;
+        self.frames_ended = false;
+        self.state.frame = None;
 
-            self.current_local_color_table[i as usize] = color565_from_rgb(r, g, b);
-        }
-        Ok(())
+        self.read_gif_metadata().map_err(|error| self.locate(error))
     }
 
-    /// Parses and consumes the initial metadata section of a GIF file
-    pub fn parse_gif_metadata(&mut self) -> Result<(), Error> {
-        self.validate_header()?;
-        let metadata = self.parse_logical_screen_descriptor()?;
+    fn read_gif_metadata(&mut self) -> Result<(), Failure<R::Error>> {
+        let version = parse_header(&self.next_bytes()?)?;
+        let metadata = parse_logical_screen_descriptor(version, &self.next_bytes()?);
+        DecoderState::<R::Format, OUT_BUF_LEN, REVERSE_BUF_LEN>::check_width(metadata.width)?;
 
-        if metadata.width > MAX_SIZE || metadata.height > MAX_SIZE {
-            return Err(Error::ImageTooBig);
-        };
         if metadata.has_global_color_table {
-            self.parse_global_color_table(metadata.global_color_table_size)?;
+            self.parse_color_table(metadata.global_color_table_size, false)?;
+        }
+        match self.state.set_file_metadata(metadata) {
+            Some(action) => action.apply(self.renderer).map_err(Failure::Render),
+            None => Ok(()),
         }
-        self.file_metadata = Some(metadata);
-
-        Ok(())
     }
 
-    pub fn get_gif_metadata(&self) -> Option<&GifFileMetadata> {
-        self.file_metadata.as_ref()
+    pub fn get_gif_metadata(&self) -> Option<&GifFileMetadata<<R::Format as PixelFormat>::Pixel>> {
+        self.state.file_metadata.as_ref()
     }
 
     // === parse frame ===
 
-    /// See GIF 89a spec section 23.
+    /// See GIF 89a spec section 26. Only the NETSCAPE2.0 looping extension is used,
+    /// everything else is skipped.
     /// Extension Introducer, label and block size already handled by caller
-    fn parse_graphics_control_extension(&mut self) -> Result<GraphicsControlExtension, Error> {
-        let packed_fields = self.next_byte()?;
-        let hundedths_delay = self.next_short()?;
-        let transparency_index = self.next_byte()?;
+    fn parse_application_extension(&mut self) -> Result<(), Error> {
+        let identifier = self.next_bytes()?;
 
-        let has_transparency = packed_fields & 1 != 0;
-
-        let terminator = self.next_byte()?;
-        if terminator != 0 {
-            return Err(Error::MissingBlockterminator);
+        let block_size = self.next_byte()?;
+        if !is_looping_extension(&identifier, block_size) {
+            return self.skip_sub_blocks(block_size);
         }
+        let sub_block = self.next_bytes()?;
+        self.state.set_loop_count(&sub_block);
 
-        Ok(GraphicsControlExtension {
-            millis_delay: hundedths_delay as u32 * 10,
-            has_transparency,
-            transparency_index,
-        })
+        let block_size = self.next_byte()?;
+        self.skip_sub_blocks(block_size)
     }
 
-    /// See GIF 89a spec section 20.
-    /// Image Separator already handled by caller
-    fn parse_image_descriptor(
-        &mut self,
-        extension: Option<GraphicsControlExtension>,
-    ) -> Result<GifFrameMetadata, Error> {
-        let xpos = self.next_short()?;
-        let ypos = self.next_short()?;
-        let width = self.next_short()?;
-        let height = self.next_short()?;
-        let packed_fields = self.next_byte()?;
-
-        let has_local_color_table = (packed_fields & 1 << 7) != 0;
-        let interlace = (packed_fields & 1 << 6) != 0;
-        let color_table_bits = packed_fields & 0b00000111;
-        let local_color_table_size = 1 << (color_table_bits + 1);
-
-        if interlace {
-            return Err(Error::InterlacingNotSupported);
-        }
+    /// consumes data sub-blocks up to and including the block terminator
+    pub(crate) fn skip_sub_blocks(&mut self, first_block_size: u8) -> Result<(), Error> {
+        let mut block_size = first_block_size;
 
-        Ok(GifFrameMetadata {
-            frame_area: ImageArea {
-                xpos,
-                ypos,
-                width,
-                height,
-            },
-            local_color_table_size,
-            has_local_color_table,
-            extension,
-        })
+        while block_size != 0 {
+            self.data_source
+                .take_bytes(block_size as usize, |_| Ok(()))?;
+            block_size = self.next_byte()?;
+        }
+        Ok(())
     }
 
     /// Parses and consumes the metadata section of the next frame, including all
     /// GIF extensions up until the actual image data.
-    /// Resturns Err(Error::GifEnded) when there is no frame left
-    pub fn parse_frame_metadata(&mut self) -> Result<(), Error> {
+    /// Returns Err(Error::GifEnded) when there is no frame left
+    pub fn parse_frame_metadata(&mut self) -> Result<(), DecodeError<R::Error>> {
+        self.state.begin_frame();
+        self.read_frame_metadata()
+            .map_err(|error| self.locate(error))
+    }
+
+    fn read_frame_metadata(&mut self) -> Result<(), Error> {
         let mut extension: Option<GraphicsControlExtension> = None;
 
         loop {
-            let block_introducer = self.next_byte()?;
-
-            match block_introducer {
-                0x2C => {
-                    // Image descriptor
-                    let metadata = self.parse_image_descriptor(extension)?;
+            match parse_block_introducer(self.next_byte()?)? {
+                Block::ImageDescriptor => {
+                    let metadata = parse_image_descriptor(&self.next_bytes()?, extension);
+                    DecoderState::<R::Format, OUT_BUF_LEN, REVERSE_BUF_LEN>::check_width(
+                        metadata.frame_area.width,
+                    )?;
 
                     if metadata.has_local_color_table {
-                        self.parse_local_color_table(metadata.local_color_table_size)?;
+                        self.parse_color_table(metadata.local_color_table_size, true)?;
                     }
-                    self.current_frame_metadata = Some(metadata);
+                    self.state.current_frame_metadata = Some(metadata);
+                    self.image_pending = true;
 
                     return Ok(()); // image data follows
                 }
-                0x21 => {
-                    // Extension
-                    let extension_label = self.next_byte()?;
-                    let mut block_size = self.next_byte()?;
-
-                    if extension_label == 0xF9 {
-                        // graphics control extension
-                        extension = Some(self.parse_graphics_control_extension()?);
-                    } else {
-                        // ignore all other extensions
-                        while block_size != 0 {
-                            for _ in 0..block_size {
-                                self.next_byte()?;
-                            }
-                            block_size = self.next_byte()?;
+                Block::Extension => {
+                    let [extension_label, block_size] = self.next_bytes()?;
+
+                    match parse_extension_label(extension_label, block_size) {
+                        Extension::GraphicsControl => {
+                            extension =
+                                Some(parse_graphics_control_extension(&self.next_bytes()?)?);
                         }
+                        Extension::Application => self.parse_application_extension()?,
+                        Extension::Skipped => self.skip_sub_blocks(block_size)?,
                     }
                 }
-                0x3B => {
-                    return Err(Error::GifEnded);
-                }
-                _ => {
-                    return Err(Error::InvalidBlockintroducer);
-                }
+                Block::Trailer => return Err(Error::GifEnded),
             }
         }
     }
 
     pub fn get_current_frame_delay_ms(&self) -> Option<u32> {
+        self.state.current_frame_delay_ms()
+    }
+
+    /// Decodes and consumes the image data of the frame.
+    /// Calls renderer.write_area() whenever the output buffer is full.
+    /// Calls renderer.flush_frame() when all images data has been written.
+    pub fn decode_frame_image(&mut self) -> Result<(), DecodeError<R::Error>> {
+        self.image_pending = false;
+        self.state
+            .decode_frame_image(&mut self.data_source, self.renderer)
+            .map_err(|error| self.locate(error))
+    }
+
+    /// Like decode_frame_image(), but draws the frame with a different renderer
+    pub fn decode_frame_image_into<T>(
+        &mut self,
+        renderer: &mut T,
+    ) -> Result<(), DecodeError<T::Error>>
+    where
+        T: ImageRenderer<Format = R::Format>,
+    {
+        self.image_pending = false;
+        self.state
+            .decode_frame_image(&mut self.data_source, renderer)
+            .map_err(|error| self.state.error_at(error, self.data_source.offset))
+    }
+
+    /// Consumes the image data of the frame without decoding it, e.g. to drop frames
+    /// when playback falls behind. The renderer is not called.
+    ///
+    /// In compositing mode the skipped frame is treated as if it was never shown:
+    /// the disposal of the frame before it stays pending and is applied before the next
+    /// decoded frame. Skipping a frame with disposal method RestoreToBackground or
+    /// RestoreToPrevious therefore does not change the following frames. The pixels of
+    /// other skipped frames are missing until a following frame covers their area.
+    pub fn skip_frame_image(&mut self) -> Result<(), DecodeError<R::Error>> {
+        self.image_pending = false;
+        self.skip_image_data().map_err(|error| self.locate(error))
+    }
+
+    pub(crate) fn skip_image_data(&mut self) -> Result<(), Error> {
+        self.next_byte()?; // LZW minimum code size
+        let block_size = self.next_byte()?;
+        self.skip_sub_blocks(block_size)
+    }
+
+    /// Parses the metadata of the next frame, like parse_frame_metadata().
+    /// Afterwards the caller decides whether to decode_frame_image() or skip_frame_image(),
+    /// if it does neither the image data is skipped by the next call.
+    /// Returns None once the trailer of the file has been reached, and on every call after.
+    /// An error is returned once, the calls after it return None as well.
+    pub fn next_frame(&mut self) -> Option<Result<FrameInfo, DecodeError<R::Error>>> {
+        if self.frames_ended {
+            return None;
+        }
+        if self.image_pending {
+            if let Err(error) = self.skip_frame_image() {
+                self.frames_ended = true;
+                return Some(Err(error));
+            }
+        }
+
+        match self.parse_frame_metadata() {
+            Ok(()) => self
+                .state
+                .current_frame_metadata
+                .as_ref()
+                .map(|metadata| Ok(FrameInfo::from(metadata))),
+            Err(error) => {
+                self.frames_ended = true;
+                match error.error() {
+                    Error::GifEnded => None,
+                    _ => Some(Err(error)),
+                }
+            }
+        }
+    }
+
+    pub fn get_data_source(&mut self) -> &mut DS {
+        &mut self.data_source.source
+    }
+}
+
+impl<'a, F: PixelFormat, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    DecoderState<'a, F, OUT_BUF_LEN, REVERSE_BUF_LEN>
+{
+    pub(crate) fn new(
+        global_color_table: &'a mut ColorTable<F>,
+        current_local_color_table: &'a mut ColorTable<F>,
+        lzw_table: &'a mut [LzwEntry; 4096],
+        reverse_buffer: &'a mut [u8; REVERSE_BUF_LEN],
+        output_buffer: &'a mut [u8; OUT_BUF_LEN],
+    ) -> Self {
+        Self {
+            file_metadata: None,
+            current_frame_metadata: None,
+            compositing: false,
+            fill_background: false,
+            pending_disposal: None,
+            frame: None,
+            global_color_table,
+            current_local_color_table,
+            lzw_table,
+            reverse_buffer,
+            output_buffer,
+        }
+    }
+
+    /// the output buffer must hold at least one line of the image
+    pub(crate) fn check_width(width: u16) -> Result<(), Error> {
+        match width as usize > OUT_BUF_LEN {
+            true => Err(Error::ImageTooBig(width)),
+            false => Ok(()),
+        }
+    }
+
+    pub(crate) fn set_color(&mut self, local: bool, index: usize, r: u8, g: u8, b: u8) {
+        let color_table = match local {
+            true => &mut self.current_local_color_table,
+            false => &mut self.global_color_table,
+        };
+        color_table[index] = F::from_rgb(r, g, b);
+    }
+
+    /// Stores the metadata once the global color table has been read.
+    /// Resolves the background color, returns the fill of the logical screen if enabled.
+    pub(crate) fn set_file_metadata(
+        &mut self,
+        mut metadata: GifFileMetadata<F::Pixel>,
+    ) -> Option<RenderAction<F::Pixel>> {
+        let background_color_index = metadata.background_color_index as usize;
+        if metadata.has_global_color_table
+            && background_color_index < metadata.global_color_table_size
+        {
+            metadata.background_color = Some(self.global_color_table[background_color_index]);
+        }
+
+        let screen_area = ImageArea {
+            xpos: 0,
+            ypos: 0,
+            width: metadata.width,
+            height: metadata.height,
+        };
+        let fill = match (self.fill_background, metadata.background_color) {
+            (true, Some(color)) => Some(RenderAction::Fill(screen_area, color)),
+            _ => None,
+        };
+        self.file_metadata = Some(metadata);
+
+        fill
+    }
+
+    /// takes the first data sub-block of the NETSCAPE2.0 extension
+    pub(crate) fn set_loop_count(&mut self, sub_block: &[u8; 3]) {
+        if let (Some(loop_count), Some(metadata)) =
+            (parse_loop_count(sub_block), &mut self.file_metadata)
+        {
+            metadata.loop_count = loop_count;
+        }
+    }
+
+    /// called before the blocks of the next frame are parsed
+    pub(crate) fn begin_frame(&mut self) {
+        self.frame = Some(self.frame.map_or(0, |frame| frame + 1));
+    }
+
+    pub(crate) fn error_at<E>(
+        &self,
+        failure: impl Into<Failure<E>>,
+        offset: usize,
+    ) -> DecodeError<E> {
+        DecodeError::new(failure.into(), offset, self.frame)
+    }
+
+    pub(crate) fn current_frame_delay_ms(&self) -> Option<u32> {
         if let Some(frame_meta) = &self.current_frame_metadata {
             if let Some(extension) = &frame_meta.extension {
-                return Some(*(&extension.millis_delay));
+                return Some(extension.millis_delay);
             }
         }
         None
     }
 
-    /// Decodes and consumes the image data of the frame.
-    /// Calls renderer.write_area() whenever the output buffer is full.
-    /// Calls renderer.flush_frame() when all images data has been written.
-    pub fn decode_frame_image(&mut self) -> Result<(), Error> {
-        // == construct frame decoder ==
-        let initial_lzw_size = self.next_byte()?;
+    fn decode_frame_image<DS, R>(
+        &mut self,
+        data_source: &mut DS,
+        renderer: &mut R,
+    ) -> Result<(), Failure<R::Error>>
+    where
+        DS: DataSource,
+        R: ImageRenderer<Format = F>,
+    {
+        let initial_lzw_size = data_source.next_byte()?;
+        let (mut lzw_state, actions) = self.start_frame_image(initial_lzw_size)?;
+        for action in actions.into_iter().flatten() {
+            action.apply(renderer).map_err(Failure::Render)?;
+        }
 
-        let metadata = self.current_frame_metadata.as_ref().unwrap();
+        let (decoder, color_table) = self.lzw_decoder(&mut lzw_state)?;
+        FrameDecoder::new(decoder, color_table, renderer).decode_frame(data_source)
+    }
+
+    /// Sets up the LZW state for the image data of the current frame.
+    /// In compositing mode also returns the renderer calls that dispose the previous
+    /// frame and save the area of this one, they need to be applied before decoding.
+    /// Fails with Error::MissingFrameMetadata if no frame metadata has been parsed yet
+    /// and with Error::InvalidLzwCodeSize if the code size is outside of 2..=11, the
+    /// symbols start one bit wider and may not exceed 12 bits.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn start_frame_image(
+        &mut self,
+        initial_lzw_size: u8,
+    ) -> Result<(LzwState, [Option<RenderAction<F::Pixel>>; 2]), Error> {
+        let metadata = self
+            .current_frame_metadata
+            .as_ref()
+            .ok_or(Error::MissingFrameMetadata)?;
+        if !(2..=11).contains(&initial_lzw_size) {
+            return Err(Error::InvalidLzwCodeSize(initial_lzw_size));
+        }
+        let mut actions = [None; 2];
+
+        if self.compositing {
+            let background = match self.fill_background {
+                true => self.file_metadata.as_ref().and_then(|m| m.background_color),
+                false => None,
+            };
+            actions[0] = disposal_action(self.pending_disposal.take(), background);
+
+            let disposal_method = match &metadata.extension {
+                Some(extension) => extension.disposal_method,
+                None => DisposalMethod::Unspecified,
+            };
+            if disposal_method == DisposalMethod::RestoreToPrevious {
+                actions[1] = Some(RenderAction::Save(metadata.frame_area));
+            }
+            self.pending_disposal = Some((disposal_method, metadata.frame_area));
+        }
+
+        let lzw_state = LzwState::new(metadata, initial_lzw_size, OUT_BUF_LEN);
+        Ok((lzw_state, actions))
+    }
+
+    /// the LZW decoder for the current frame continuing from lzw_state,
+    /// together with the color table of the frame
+    pub(crate) fn lzw_decoder<'s>(
+        &'s mut self,
+        lzw_state: &'s mut LzwState,
+    ) -> Result<
+        (
+            LzwDecoder<'s, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+            &'s ColorTable<F>,
+        ),
+        Error,
+    > {
+        let metadata = self
+            .current_frame_metadata
+            .as_ref()
+            .ok_or(Error::MissingFrameMetadata)?;
 
         let color_table = match metadata.has_local_color_table {
-            true => &mut self.current_local_color_table,
-            false => &mut self.global_color_table,
+            true => &self.current_local_color_table,
+            false => &self.global_color_table,
         };
 
-        let mut frame_decoder = FrameDecoder::new(
-            &mut self.data_source,
+        let decoder = LzwDecoder::new(
+            lzw_state,
             metadata,
-            *color_table,
             self.lzw_table,
             self.reverse_buffer,
             self.output_buffer,
-            self.renderer,
-            initial_lzw_size,
         );
+        Ok((decoder, color_table))
+    }
+}
 
-        frame_decoder.decode_frame()
+/// disposes the area of the previous frame before the next one is drawn
+fn disposal_action<P>(
+    disposal: Option<(DisposalMethod, ImageArea)>,
+    background: Option<P>,
+) -> Option<RenderAction<P>> {
+    match (disposal, background) {
+        (Some((DisposalMethod::RestoreToBackground, area)), Some(color)) => {
+            Some(RenderAction::Fill(area, color))
+        }
+        (Some((DisposalMethod::RestoreToBackground, area)), None) => {
+            Some(RenderAction::Clear(area))
+        }
+        (Some((DisposalMethod::RestoreToPrevious, area)), _) => Some(RenderAction::Restore(area)),
+        _ => None,
     }
+}
 
-    pub fn get_data_source(&mut self) -> &mut DS {
-        &mut self.data_source
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    R: ImageRenderer,
+{
+    /// adds the current position to an error
+    pub(crate) fn locate(&self, error: impl Into<Failure<R::Error>>) -> DecodeError<R::Error> {
+        self.state.error_at(error, self.data_source.offset)
     }
 }
 
 // optional rewind capability of datasource
-impl<'a, DS, R> GifDecoder<'a, DS, R>
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
 where
     DS: Rewindable,
+    R: ImageRenderer,
 {
-    pub fn rewind(&mut self) -> Result<(), Error> {
-        self.data_source.rewind()
+    pub fn rewind(&mut self) -> Result<(), DecodeError<R::Error>> {
+        self.image_pending = false;
+        self.frames_ended = false;
+        self.state.frame = None;
+        self.data_source
+            .rewind()
+            .map_err(|error| self.locate(error))
+    }
+}
+
+// playback of the whole animation, needs to rewind for looping
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource + Rewindable,
+    R: ImageRenderer,
+{
+    /// Plays the animation from the start as often as its loop count says.
+    /// wait() is called with the delay of each frame after it has been decoded,
+    /// e.g. to sleep until the next frame is due. Playback stops when it returns false.
+    /// A file without frames is played once, even if it loops infinitely.
+    pub fn play<F>(&mut self, mut wait: F) -> Result<(), DecodeError<R::Error>>
+    where
+        F: FnMut(u32) -> bool,
+    {
+        let mut completed_plays = 0;
+
+        loop {
+            self.rewind()?;
+            self.parse_gif_metadata()?;
+
+            let mut frames = 0;
+            while let Some(frame) = self.next_frame() {
+                let frame = frame?;
+                self.decode_frame_image()?;
+                frames += 1;
+
+                if !wait(frame.delay_ms) {
+                    return Ok(());
+                }
+            }
+            if frames == 0 {
+                return Ok(());
+            }
+            completed_plays += 1;
+
+            let loop_count = match &self.state.file_metadata {
+                Some(metadata) => metadata.loop_count,
+                None => LoopCount::Once,
+            };
+            if !loop_count.should_repeat(completed_plays) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// === block parsers, shared with the PushDecoder ===
+
+/// the block that follows a block introducer, see GIF 89a spec section 15
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Block {
+    ImageDescriptor,
+    Extension,
+    Trailer,
+}
+
+pub(crate) fn parse_block_introducer(block_introducer: u8) -> Result<Block, Error> {
+    match block_introducer {
+        0x2C => Ok(Block::ImageDescriptor),
+        0x21 => Ok(Block::Extension),
+        0x3B => Ok(Block::Trailer),
+        byte => Err(Error::InvalidBlockintroducer(byte)),
+    }
+}
+
+/// how an extension continues after its label and the size of its first block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Extension {
+    /// the 5 remaining bytes of the graphics control extension follow
+    GraphicsControl,
+    /// the 11 bytes of the application identifier follow
+    Application,
+    /// all other extensions are skipped, starting with a data sub-block of block size
+    Skipped,
+}
+
+pub(crate) fn parse_extension_label(extension_label: u8, block_size: u8) -> Extension {
+    match (extension_label, block_size) {
+        (0xF9, _) => Extension::GraphicsControl,
+        (0xFF, 11) => Extension::Application,
+        _ => Extension::Skipped,
+    }
+}
+
+/// verify that the magic number and version of the gif file are correct.
+/// GIF87a is a subset of GIF89a, so both are decoded the same way
+pub(crate) fn parse_header(header: &[u8; 6]) -> Result<GifVersion, Error> {
+    match from_utf8(header) {
+        Ok("GIF87a") => Ok(GifVersion::Gif87a),
+        Ok("GIF89a") => Ok(GifVersion::Gif89a),
+        _ => Err(Error::WrongFiletype),
+    }
+}
+
+/// see GIF 89a spec section 18. Parses LogicalScreenDescriptor into gifMetadata
+pub(crate) fn parse_logical_screen_descriptor<P>(
+    version: GifVersion,
+    descriptor: &[u8; 7],
+) -> GifFileMetadata<P> {
+    let [w0, w1, h0, h1, packed_fields, background_color_index, _aspect_ratio] = *descriptor;
+
+    let has_global_color_table = (packed_fields & 1 << 7) != 0;
+    let table_bits = (packed_fields & 0b00000111) + 1;
+    let global_color_table_size = 1 << table_bits;
+
+    GifFileMetadata {
+        version,
+        width: u16::from_le_bytes([w0, w1]),
+        height: u16::from_le_bytes([h0, h1]),
+        has_global_color_table,
+        global_color_table_size,
+        background_color_index,
+        background_color: None, // resolved once the global color table is read
+        loop_count: LoopCount::Once,
+    }
+}
+
+/// See GIF 89a spec section 23.
+/// Extension Introducer, label and block size already handled by caller
+pub(crate) fn parse_graphics_control_extension(
+    block: &[u8; 5],
+) -> Result<GraphicsControlExtension, Error> {
+    let [packed_fields, d0, d1, transparency_index, terminator] = *block;
+
+    if terminator != 0 {
+        return Err(Error::MissingBlockterminator);
+    }
+
+    let hundedths_delay = u16::from_le_bytes([d0, d1]);
+    let disposal_method = DisposalMethod::from_packed_fields(packed_fields);
+    let has_transparency = packed_fields & 1 != 0;
+
+    Ok(GraphicsControlExtension {
+        millis_delay: hundedths_delay as u32 * 10,
+        disposal_method,
+        has_transparency,
+        transparency_index,
+    })
+}
+
+/// whether an application extension holds the loop count,
+/// block_size is the size of the sub-block following the identifier
+pub(crate) fn is_looping_extension(identifier: &[u8; 11], block_size: u8) -> bool {
+    (identifier == b"NETSCAPE2.0" || identifier == b"ANIMEXTS1.0") && block_size == 3
+}
+
+/// the sub-block of the looping extension, None for unknown sub-block ids
+pub(crate) fn parse_loop_count(sub_block: &[u8; 3]) -> Option<LoopCount> {
+    match *sub_block {
+        [1, 0, 0] => Some(LoopCount::Infinite),
+        [1, l0, l1] => Some(LoopCount::Repeat(u16::from_le_bytes([l0, l1]))),
+        _ => None,
+    }
+}
+
+/// See GIF 89a spec section 20.
+/// Image Separator already handled by caller
+pub(crate) fn parse_image_descriptor(
+    descriptor: &[u8; 9],
+    extension: Option<GraphicsControlExtension>,
+) -> GifFrameMetadata {
+    let [x0, x1, y0, y1, w0, w1, h0, h1, packed_fields] = *descriptor;
+
+    let has_local_color_table = (packed_fields & 1 << 7) != 0;
+    let interlaced = (packed_fields & 1 << 6) != 0;
+    let color_table_bits = packed_fields & 0b00000111;
+    let local_color_table_size = 1 << (color_table_bits + 1);
+
+    GifFrameMetadata {
+        frame_area: ImageArea {
+            xpos: u16::from_le_bytes([x0, x1]),
+            ypos: u16::from_le_bytes([y0, y1]),
+            width: u16::from_le_bytes([w0, w1]),
+            height: u16::from_le_bytes([h0, h1]),
+        },
+        local_color_table_size,
+        has_local_color_table,
+        interlaced,
+        extension,
     }
 }
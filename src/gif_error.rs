@@ -1,15 +1,145 @@
-#[derive(Debug)]
+use core::convert::Infallible;
+use core::fmt;
+
+/// What went wrong while decoding
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     FileEnded,
     WrongFiletype,
-    ImageTooBig,
+    /// the width of the image or frame is larger than the output buffer
+    ImageTooBig(u16),
     MissingBlockterminator,
-    InvalidBlockintroducer,
-    InterlacingNotSupported,
+    /// the byte found where a block was expected
+    InvalidBlockintroducer(u8),
     GifEnded,
-    InvalidSymbol,
+    /// the LZW symbol that is not in the table yet
+    InvalidSymbol(u16),
+    /// the LZW minimum code size of a frame, outside of 2..=11
+    InvalidLzwCodeSize(u8),
     DecoderAlreadyFinished,
     ReverseBufferOverflow,
+    /// the renderer failed, its error is in DecodeError::render_error()
     RenderError,
     RewindError,
+    ReadError,
+    SeekError,
+    FrameNotFound,
+    MissingFrameMetadata,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::FileEnded => write!(f, "unexpected end of file"),
+            Error::WrongFiletype => write!(f, "not a GIF file"),
+            Error::ImageTooBig(width) => {
+                write!(f, "width {} does not fit into the output buffer", width)
+            }
+            Error::MissingBlockterminator => write!(f, "missing block terminator"),
+            Error::InvalidBlockintroducer(byte) => {
+                write!(f, "invalid block introducer 0x{:02X}", byte)
+            }
+            Error::GifEnded => write!(f, "no frame left"),
+            Error::InvalidSymbol(symbol) => write!(f, "invalid LZW symbol {}", symbol),
+            Error::InvalidLzwCodeSize(size) => write!(f, "invalid LZW minimum code size {}", size),
+            Error::DecoderAlreadyFinished => write!(f, "frame has already been decoded"),
+            Error::ReverseBufferOverflow => write!(f, "LZW chain longer than the reverse buffer"),
+            Error::RenderError => write!(f, "renderer failed"),
+            Error::RewindError => write!(f, "data source can not be rewound"),
+            Error::ReadError => write!(f, "data source read failed"),
+            Error::SeekError => write!(f, "data source seek failed"),
+            Error::FrameNotFound => write!(f, "frame not in the index"),
+            Error::MissingFrameMetadata => write!(f, "frame metadata has not been parsed"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// Error returned by the decoders, with the position in the file where it was detected.
+/// E is the error type of the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DecodeError<E = Infallible> {
+    pub failure: Failure<E>,
+    /// Offset in the file right after the field the error was detected in.
+    /// Errors in the image data point to the start of the data being decoded.
+    pub offset: usize,
+    /// index of the frame, None while the file metadata is parsed
+    pub frame: Option<usize>,
+}
+
+impl<E> DecodeError<E> {
+    pub(crate) fn new(failure: Failure<E>, offset: usize, frame: Option<usize>) -> Self {
+        DecodeError {
+            failure,
+            offset,
+            frame,
+        }
+    }
+
+    /// what went wrong, Error::RenderError if the renderer failed
+    pub fn error(&self) -> Error {
+        match self.failure {
+            Failure::Decode(error) => error,
+            Failure::Render(_) => Error::RenderError,
+        }
+    }
+
+    /// the error of the renderer if it failed
+    pub fn render_error(&self) -> Option<&E> {
+        match &self.failure {
+            Failure::Decode(_) => None,
+            Failure::Render(render_error) => Some(render_error),
+        }
+    }
+
+    /// drops the renderer error, e.g. to store errors of different renderers
+    pub fn without_render_error(self) -> DecodeError {
+        DecodeError {
+            failure: Failure::Decode(self.error()),
+            offset: self.offset,
+            frame: self.frame,
+        }
+    }
+}
+
+/// The renderer error is not part of the message, it is the source() of the error
+impl<E> fmt::Display for DecodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.error(), self.offset)?;
+        match self.frame {
+            Some(frame) => write!(f, " in frame {}", frame),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<E> core::error::Error for DecodeError<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        // decoding errors are already the message itself
+        match &self.failure {
+            Failure::Decode(_) => None,
+            Failure::Render(render_error) => Some(render_error),
+        }
+    }
+}
+
+/// What went wrong in a DecodeError. Only failures of the renderer carry its error,
+/// a DecodeError without renderer error has Failure::Decode(Error::RenderError)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Failure<E = Infallible> {
+    Decode(Error),
+    Render(E),
+}
+
+impl<E> From<Error> for Failure<E> {
+    fn from(error: Error) -> Self {
+        Failure::Decode(error)
+    }
 }
@@ -1,8 +1,22 @@
 #![no_std]
-#![feature(iter_next_chunk)]
 
+#[cfg(feature = "async")]
+pub mod async_decoder;
+pub mod compositor;
+pub mod data_source;
+#[cfg(feature = "embedded-graphics")]
+pub mod draw_target;
 pub mod frame_decoder;
+pub mod frame_index;
 pub mod gif_decoder;
 pub mod gif_error;
+pub mod pixel_format;
+pub mod player;
+pub mod push_decoder;
+#[cfg(feature = "embedded-io")]
+pub mod read_source;
 pub mod renderer;
+pub mod scaler;
+pub mod transform;
+pub mod typed_decoder;
 pub mod util;
@@ -0,0 +1,91 @@
+use crate::util::color565_from_rgb;
+
+/// Converts the 24 bit RGB colors of the GIF color tables into the pixel format of a display.
+/// The conversion is done once per color table entry, the renderer receives the
+/// converted color table in ImageRenderer::write_area().
+pub trait PixelFormat {
+    type Pixel: Copy + Default;
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self::Pixel;
+}
+
+/// A color table converted into the pixel format F
+pub type ColorTable<F> = [<F as PixelFormat>::Pixel; 256];
+
+/// 16 bit RGB565 as a native integer, e.g. for embedded-graphics
+pub struct Rgb565;
+
+impl PixelFormat for Rgb565 {
+    type Pixel = u16;
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self::Pixel {
+        color565_from_rgb(r, g, b)
+    }
+}
+
+/// 16 bit RGB565 in big endian byte order, as sent over SPI to e.g. the ST7789
+pub struct Rgb565Be;
+
+impl PixelFormat for Rgb565Be {
+    type Pixel = [u8; 2];
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self::Pixel {
+        color565_from_rgb(r, g, b).to_be_bytes()
+    }
+}
+
+/// 16 bit RGB565 in little endian byte order
+pub struct Rgb565Le;
+
+impl PixelFormat for Rgb565Le {
+    type Pixel = [u8; 2];
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self::Pixel {
+        color565_from_rgb(r, g, b).to_le_bytes()
+    }
+}
+
+/// 16 bit BGR565 as a native integer, for panels with swapped red and blue channels
+pub struct Bgr565;
+
+impl PixelFormat for Bgr565 {
+    type Pixel = u16;
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self::Pixel {
+        color565_from_rgb(b, g, r)
+    }
+}
+
+/// 18 bit RGB666 as three bytes with the color in the upper 6 bits, e.g. for the SSD1351
+pub struct Rgb666;
+
+impl PixelFormat for Rgb666 {
+    type Pixel = [u8; 3];
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self::Pixel {
+        [r & 0xFC, g & 0xFC, b & 0xFC]
+    }
+}
+
+/// 24 bit RGB888, the unchanged colors of the color table
+pub struct Rgb888;
+
+impl PixelFormat for Rgb888 {
+    type Pixel = [u8; 3];
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self::Pixel {
+        [r, g, b]
+    }
+}
+
+/// 8 bit grayscale luma (ITU-R BT.601 weights). Renderers for 1 bit or 4 level
+/// displays can threshold or dither it further.
+pub struct Gray8;
+
+impl PixelFormat for Gray8 {
+    type Pixel = u8;
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self::Pixel {
+        ((r as u16 * 77 + g as u16 * 150 + b as u16 * 29) >> 8) as u8
+    }
+}
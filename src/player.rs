@@ -0,0 +1,184 @@
+use crate::data_source::DataSource;
+use crate::gif_decoder::{GifDecoder, LoopCount, Rewindable};
+use crate::gif_error::{DecodeError, Error};
+use crate::renderer::ImageRenderer;
+
+/// Monotonic time source of the GifPlayer in milliseconds.
+/// The value may wrap around, only differences between two readings are used.
+pub trait Clock {
+    fn now_ms(&mut self) -> u32;
+}
+
+impl<F> Clock for F
+where
+    F: FnMut() -> u32,
+{
+    fn now_ms(&mut self) -> u32 {
+        self()
+    }
+}
+
+/// Result of GifPlayer::poll()
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerStatus {
+    /// a frame has been decoded, the next one is due in next_in_ms
+    FrameDecoded { next_in_ms: u32 },
+    /// the current frame is still shown, the next one is due in next_in_ms
+    Waiting { next_in_ms: u32 },
+    /// the animation has been played as often as its loop count says
+    Finished,
+}
+
+impl PlayerStatus {
+    /// time until poll() should be called again, None when playback has finished
+    pub fn next_in_ms(&self) -> Option<u32> {
+        match self {
+            PlayerStatus::FrameDecoded { next_in_ms } | PlayerStatus::Waiting { next_in_ms } => {
+                Some(*next_in_ms)
+            }
+            PlayerStatus::Finished => None,
+        }
+    }
+}
+
+/// Non-blocking animation player built on GifDecoder.
+/// Call poll() or poll_at(now) from the application loop, it decodes the next frame once the
+/// delay of the current frame has elapsed and loops the animation according to its
+/// loop count by rewinding the data source.
+///
+/// Frame times are scheduled relative to when the previous frame was due, so delays
+/// do not accumulate. If the player falls behind by more than a frame, the schedule
+/// restarts from the current time instead of decoding frames in a burst.
+pub struct GifPlayer<'a, DS, R, C, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+where
+    R: ImageRenderer,
+{
+    decoder: GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+    clock: C,
+    started: bool,
+    finished: bool,
+    completed_plays: u32,
+    /// whether a frame has been decoded since the last rewind
+    pass_has_frames: bool,
+    next_frame_due: Option<u32>,
+}
+
+impl<'a, DS, R, C, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    GifPlayer<'a, DS, R, C, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource + Rewindable,
+    R: ImageRenderer,
+    C: Clock,
+{
+    /// takes a freshly constructed decoder, the file metadata is parsed by the first poll()
+    pub fn new(decoder: GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>, clock: C) -> Self {
+        Self {
+            decoder,
+            clock,
+            started: false,
+            finished: false,
+            completed_plays: 0,
+            pass_has_frames: false,
+            next_frame_due: None,
+        }
+    }
+
+    /// Decodes the next frame if it is due, the current time is read from the Clock
+    /// passed to new(). Never blocks longer than decoding one frame.
+    pub fn poll(&mut self) -> Result<PlayerStatus, DecodeError<R::Error>> {
+        let now = self.clock.now_ms();
+        self.poll_at(now)
+    }
+
+    /// Decodes the next frame if it is due at now, in milliseconds.
+    /// The schedule compares the times across calls, so they have to come from the same
+    /// monotonic source, which may wrap around.
+    pub fn poll_at(&mut self, now: u32) -> Result<PlayerStatus, DecodeError<R::Error>> {
+        if self.finished {
+            return Ok(PlayerStatus::Finished);
+        }
+
+        if let Some(due) = self.next_frame_due {
+            let remaining = due.wrapping_sub(now) as i32;
+            if remaining > 0 {
+                return Ok(PlayerStatus::Waiting {
+                    next_in_ms: remaining as u32,
+                });
+            }
+        }
+
+        if !self.decode_next_frame()? {
+            self.finished = true;
+            return Ok(PlayerStatus::Finished);
+        }
+
+        let delay = self.decoder.get_current_frame_delay_ms().unwrap_or(0);
+        let start = match self.next_frame_due {
+            Some(due) if now.wrapping_sub(due) < delay => due,
+            _ => now,
+        };
+        let due = start.wrapping_add(delay);
+        self.next_frame_due = Some(due);
+
+        Ok(PlayerStatus::FrameDecoded {
+            next_in_ms: due.wrapping_sub(now),
+        })
+    }
+
+    /// Starts playback from the first frame again, the next poll() decodes it immediately
+    pub fn restart(&mut self) {
+        self.started = false;
+        self.finished = false;
+        self.completed_plays = 0;
+        self.next_frame_due = None;
+    }
+
+    pub fn get_decoder(&mut self) -> &mut GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN> {
+        &mut self.decoder
+    }
+
+    pub fn into_decoder(self) -> GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN> {
+        self.decoder
+    }
+
+    /// returns false when there are no frames left to play
+    fn decode_next_frame(&mut self) -> Result<bool, DecodeError<R::Error>> {
+        if !self.started {
+            self.start_from_beginning()?;
+            self.started = true;
+        }
+
+        match self.decoder.parse_frame_metadata() {
+            Ok(()) => {}
+            Err(error) if error.error() == Error::GifEnded => {
+                // a file without frames would otherwise be rewound forever
+                if !self.pass_has_frames {
+                    return Ok(false);
+                }
+                self.completed_plays += 1;
+
+                let loop_count = match self.decoder.get_gif_metadata() {
+                    Some(metadata) => metadata.loop_count,
+                    None => LoopCount::Once,
+                };
+                if !loop_count.should_repeat(self.completed_plays) {
+                    return Ok(false);
+                }
+
+                self.start_from_beginning()?;
+                self.decoder.parse_frame_metadata()?;
+            }
+            Err(error) => return Err(error),
+        }
+
+        self.decoder.decode_frame_image()?;
+        self.pass_has_frames = true;
+        Ok(true)
+    }
+
+    fn start_from_beginning(&mut self) -> Result<(), DecodeError<R::Error>> {
+        self.pass_has_frames = false;
+        self.decoder.rewind()?;
+        self.decoder.parse_gif_metadata()
+    }
+}
@@ -0,0 +1,367 @@
+use crate::frame_decoder::{FrameDecoder, GraphicsControlExtension, LzwEntry, LzwState};
+use crate::gif_decoder::{
+    is_looping_extension, parse_block_introducer, parse_extension_label,
+    parse_graphics_control_extension, parse_header, parse_image_descriptor,
+    parse_logical_screen_descriptor, Block, DecoderState, Extension, GifFileMetadata, GifVersion,
+};
+use crate::gif_error::{DecodeError, Failure};
+use crate::pixel_format::{ColorTable, PixelFormat};
+use crate::renderer::ImageRenderer;
+
+/// Result of PushDecoder::feed()
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// the whole chunk has been consumed, feed the next one
+    NeedMoreData,
+    /// A frame has been decoded and flushed. Only the first consumed bytes of the chunk
+    /// have been used, the rest needs to be fed again.
+    FrameDecoded { consumed: usize },
+    /// The trailer of the file has been reached after consumed bytes of the chunk.
+    /// Everything after it is ignored, use reset() to decode the file again.
+    Finished { consumed: usize },
+}
+
+/// Position of the decoder in the block structure of the file
+#[derive(Clone, Copy)]
+enum BlockState {
+    Header,
+    LogicalScreenDescriptor(GifVersion),
+    /// index of the next color table entry
+    GlobalColorTable(usize),
+    BlockIntroducer,
+    /// extension label and block size
+    ExtensionHeader,
+    GraphicsControlExtension,
+    /// identifier and size of the first data sub-block
+    ApplicationIdentifier,
+    LoopingSubBlock,
+    SubBlockSize,
+    /// bytes left in a sub-block that is skipped
+    SubBlockData(u8),
+    ImageDescriptor,
+    LocalColorTable(usize),
+    LzwMinimumCodeSize,
+    ImageSubBlockSize(LzwState),
+    ImageSubBlockData(LzwState, u8),
+    Ended,
+}
+
+/// size of the longest fixed size field, the application identifier plus block size
+const MAX_FIELD_LEN: usize = 12;
+
+/// Push based variant of GifDecoder for data that arrives in chunks, e.g. from DMA
+/// transfers or a network connection. The caller passes each chunk to feed() as it
+/// arrives. Chunks can end anywhere, the decoder keeps the partially read header fields
+/// and the LZW state and continues with the next chunk. Nothing is read ahead, so
+/// the chunk buffers can be reused as soon as feed() returns.
+///
+/// Rendering works like with GifDecoder, including the compositing and background
+/// options, the buffers and their sizes are also the same.
+pub struct PushDecoder<'a, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+where
+    R: ImageRenderer,
+{
+    renderer: &'a mut R,
+    state: DecoderState<'a, R::Format, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+    block_state: BlockState,
+    /// a fixed size field that is split across chunks
+    field: [u8; MAX_FIELD_LEN],
+    field_len: usize,
+    extension: Option<GraphicsControlExtension>,
+    /// offset in the file of the next byte that is fed
+    offset: usize,
+}
+
+impl<'a, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    PushDecoder<'a, R, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    R: ImageRenderer,
+{
+    /// buffers need to be passed in from outside so that this object still fits on the stack
+    pub fn new(
+        renderer: &'a mut R,
+        buf_a: &'a mut ColorTable<R::Format>,
+        buf_b: &'a mut ColorTable<R::Format>,
+        buf_c: &'a mut [LzwEntry; 4096],
+        buf_d: &'a mut [u8; REVERSE_BUF_LEN],
+        buf_e: &'a mut [u8; OUT_BUF_LEN],
+    ) -> Self {
+        PushDecoder {
+            renderer,
+            state: DecoderState::new(buf_a, buf_b, buf_c, buf_d, buf_e),
+            block_state: BlockState::Header,
+            field: [0; MAX_FIELD_LEN],
+            field_len: 0,
+            extension: None,
+            offset: 0,
+        }
+    }
+
+    /// Enables honouring the disposal methods of frames, see ImageRenderer::clear_area()
+    pub fn set_compositing(&mut self, enabled: bool) {
+        self.state.compositing = enabled;
+        self.state.pending_disposal = None;
+    }
+
+    /// Enables filling with the background color of the file, see ImageRenderer::fill_area().
+    /// Needs to be set before the logical screen descriptor is fed.
+    pub fn set_fill_background(&mut self, enabled: bool) {
+        self.state.fill_background = enabled;
+    }
+
+    /// starts over, the next chunk is expected to begin with the GIF header
+    pub fn reset(&mut self) {
+        self.block_state = BlockState::Header;
+        self.field_len = 0;
+        self.extension = None;
+        self.offset = 0;
+        self.state.pending_disposal = None;
+        self.state.frame = None;
+    }
+
+    /// available once the global color table has been fed
+    pub fn get_gif_metadata(&self) -> Option<&GifFileMetadata<<R::Format as PixelFormat>::Pixel>> {
+        self.state.file_metadata.as_ref()
+    }
+
+    /// delay of the last frame that has been started
+    pub fn get_current_frame_delay_ms(&self) -> Option<u32> {
+        self.state.current_frame_delay_ms()
+    }
+
+    /// Decodes the next chunk of the file.
+    /// Stops after each completed frame, so that the caller can wait for its delay.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Progress, DecodeError<R::Error>> {
+        let mut consumed = 0;
+
+        loop {
+            if let BlockState::Ended = self.block_state {
+                return Ok(Progress::Finished { consumed });
+            }
+            if consumed == chunk.len() {
+                return Ok(Progress::NeedMoreData);
+            }
+
+            let (count, result) = self.step(&chunk[consumed..]);
+            consumed += count;
+            self.offset += count;
+            let frame_decoded = result.map_err(|error| self.state.error_at(error, self.offset))?;
+
+            if frame_decoded {
+                return Ok(Progress::FrameDecoded { consumed });
+            }
+        }
+    }
+
+    /// Consumes bytes for the current block state, at least one.
+    /// Returns the number of consumed bytes, also when the block is invalid,
+    /// and whether a frame has been completed.
+    fn step(&mut self, bytes: &[u8]) -> (usize, Result<bool, Failure<R::Error>>) {
+        match self.block_state {
+            BlockState::Header => self.step_field(bytes, |decoder, header| {
+                let version = parse_header(header)?;
+                decoder.block_state = BlockState::LogicalScreenDescriptor(version);
+                Ok(())
+            }),
+            BlockState::LogicalScreenDescriptor(version) => {
+                self.step_field(bytes, |decoder, descriptor| {
+                    let metadata = parse_logical_screen_descriptor(version, descriptor);
+                    DecoderState::<R::Format, OUT_BUF_LEN, REVERSE_BUF_LEN>::check_width(
+                        metadata.width,
+                    )?;
+
+                    let has_global_color_table = metadata.has_global_color_table;
+                    decoder.state.file_metadata = Some(metadata);
+
+                    match has_global_color_table {
+                        true => decoder.block_state = BlockState::GlobalColorTable(0),
+                        false => decoder.finish_file_metadata()?,
+                    }
+                    Ok(())
+                })
+            }
+            BlockState::GlobalColorTable(index) => self.step_field(bytes, |decoder, &[r, g, b]| {
+                decoder.state.set_color(false, index, r, g, b);
+
+                let size = match &decoder.state.file_metadata {
+                    Some(metadata) => metadata.global_color_table_size,
+                    None => 0,
+                };
+                match index + 1 < size {
+                    true => decoder.block_state = BlockState::GlobalColorTable(index + 1),
+                    false => decoder.finish_file_metadata()?,
+                }
+                Ok(())
+            }),
+            BlockState::BlockIntroducer => {
+                self.block_state = match parse_block_introducer(bytes[0]) {
+                    Ok(Block::ImageDescriptor) => BlockState::ImageDescriptor,
+                    Ok(Block::Extension) => BlockState::ExtensionHeader,
+                    Ok(Block::Trailer) => BlockState::Ended,
+                    Err(error) => return (1, Err(error.into())),
+                };
+                (1, Ok(false))
+            }
+            BlockState::ExtensionHeader => {
+                self.step_field(bytes, |decoder, &[extension_label, block_size]| {
+                    decoder.block_state = match parse_extension_label(extension_label, block_size) {
+                        Extension::GraphicsControl => BlockState::GraphicsControlExtension,
+                        Extension::Application => BlockState::ApplicationIdentifier,
+                        Extension::Skipped => skip_sub_blocks(block_size),
+                    };
+                    Ok(())
+                })
+            }
+            BlockState::GraphicsControlExtension => self.step_field(bytes, |decoder, block| {
+                decoder.extension = Some(parse_graphics_control_extension(block)?);
+                decoder.block_state = BlockState::BlockIntroducer;
+                Ok(())
+            }),
+            BlockState::ApplicationIdentifier => {
+                self.step_field(bytes, |decoder, field: &[u8; 12]| {
+                    let mut identifier = [0u8; 11];
+                    identifier.copy_from_slice(&field[..11]);
+                    let block_size = field[11];
+
+                    decoder.block_state = match is_looping_extension(&identifier, block_size) {
+                        true => BlockState::LoopingSubBlock,
+                        false => skip_sub_blocks(block_size),
+                    };
+                    Ok(())
+                })
+            }
+            BlockState::LoopingSubBlock => self.step_field(bytes, |decoder, sub_block| {
+                decoder.state.set_loop_count(sub_block);
+                decoder.block_state = BlockState::SubBlockSize;
+                Ok(())
+            }),
+            BlockState::SubBlockSize => {
+                self.block_state = skip_sub_blocks(bytes[0]);
+                (1, Ok(false))
+            }
+            BlockState::SubBlockData(remaining) => {
+                let count = bytes.len().min(remaining as usize);
+                self.block_state = match remaining as usize - count {
+                    0 => BlockState::SubBlockSize,
+                    left => BlockState::SubBlockData(left as u8),
+                };
+                (count, Ok(false))
+            }
+            BlockState::ImageDescriptor => self.step_field(bytes, |decoder, descriptor| {
+                let metadata = parse_image_descriptor(descriptor, decoder.extension.take());
+                DecoderState::<R::Format, OUT_BUF_LEN, REVERSE_BUF_LEN>::check_width(
+                    metadata.frame_area.width,
+                )?;
+
+                decoder.block_state = match metadata.has_local_color_table {
+                    true => BlockState::LocalColorTable(0),
+                    false => BlockState::LzwMinimumCodeSize,
+                };
+                decoder.state.current_frame_metadata = Some(metadata);
+                Ok(())
+            }),
+            BlockState::LocalColorTable(index) => self.step_field(bytes, |decoder, &[r, g, b]| {
+                decoder.state.set_color(true, index, r, g, b);
+
+                let size = match &decoder.state.current_frame_metadata {
+                    Some(metadata) => metadata.local_color_table_size,
+                    None => 0,
+                };
+                decoder.block_state = match index + 1 < size {
+                    true => BlockState::LocalColorTable(index + 1),
+                    false => BlockState::LzwMinimumCodeSize,
+                };
+                Ok(())
+            }),
+            BlockState::LzwMinimumCodeSize => (1, self.start_image(bytes[0]).map(|()| false)),
+            BlockState::ImageSubBlockSize(lzw_state) => {
+                // the block terminator ends the image data
+                self.block_state = match bytes[0] {
+                    0 => {
+                        self.state.begin_frame();
+                        BlockState::BlockIntroducer
+                    }
+                    block_size => BlockState::ImageSubBlockData(lzw_state, block_size),
+                };
+                (1, Ok(bytes[0] == 0))
+            }
+            BlockState::ImageSubBlockData(mut lzw_state, remaining) => {
+                let count = bytes.len().min(remaining as usize);
+                // errors point to the start of the bytes of the sub-block
+                if let Err(error) = self.decode_image_bytes(&mut lzw_state, &bytes[..count]) {
+                    return (0, Err(error));
+                }
+
+                self.block_state = match remaining as usize - count {
+                    0 => BlockState::ImageSubBlockSize(lzw_state),
+                    left => BlockState::ImageSubBlockData(lzw_state, left as u8),
+                };
+                (count, Ok(false))
+            }
+            BlockState::Ended => (0, Ok(false)),
+        }
+    }
+
+    /// Collects a field of N bytes, which might be split across chunks,
+    /// and calls on_field once it is complete
+    fn step_field<const N: usize, F>(
+        &mut self,
+        bytes: &[u8],
+        on_field: F,
+    ) -> (usize, Result<bool, Failure<R::Error>>)
+    where
+        F: FnOnce(&mut Self, &[u8; N]) -> Result<(), Failure<R::Error>>,
+    {
+        let count = bytes.len().min(N - self.field_len);
+        self.field[self.field_len..self.field_len + count].copy_from_slice(&bytes[..count]);
+        self.field_len += count;
+
+        if self.field_len == N {
+            self.field_len = 0;
+
+            let mut field = [0u8; N];
+            field.copy_from_slice(&self.field[..N]);
+            if let Err(error) = on_field(self, &field) {
+                return (count, Err(error));
+            }
+        }
+        (count, Ok(false))
+    }
+
+    fn start_image(&mut self, initial_lzw_size: u8) -> Result<(), Failure<R::Error>> {
+        let (lzw_state, actions) = self.state.start_frame_image(initial_lzw_size)?;
+        for action in actions.into_iter().flatten() {
+            action.apply(self.renderer).map_err(Failure::Render)?;
+        }
+        self.block_state = BlockState::ImageSubBlockSize(lzw_state);
+        Ok(())
+    }
+
+    fn decode_image_bytes(
+        &mut self,
+        lzw_state: &mut LzwState,
+        bytes: &[u8],
+    ) -> Result<(), Failure<R::Error>> {
+        let (decoder, color_table) = self.state.lzw_decoder(lzw_state)?;
+        FrameDecoder::new(decoder, color_table, self.renderer).process_bytes(bytes)
+    }
+
+    fn finish_file_metadata(&mut self) -> Result<(), Failure<R::Error>> {
+        if let Some(metadata) = self.state.file_metadata.take() {
+            if let Some(action) = self.state.set_file_metadata(metadata) {
+                action.apply(self.renderer).map_err(Failure::Render)?;
+            }
+        }
+        self.state.begin_frame();
+        self.block_state = BlockState::BlockIntroducer;
+        Ok(())
+    }
+}
+
+/// state for skipping data sub-blocks, starting with the size of the first one
+fn skip_sub_blocks(block_size: u8) -> BlockState {
+    match block_size {
+        0 => BlockState::BlockIntroducer,
+        size => BlockState::SubBlockData(size),
+    }
+}
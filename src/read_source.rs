@@ -0,0 +1,129 @@
+use crate::data_source::{DataSource, Seekable};
+use crate::gif_decoder::Rewindable;
+use crate::gif_error::Error;
+use embedded_io::{Read, Seek, SeekFrom};
+
+/// Data source for GifDecoder that reads from an embedded-io reader, e.g. a file on an
+/// SD card or an SPI flash driver. The reader is called once per block of the size of
+/// the buffer, the decoder then takes the bytes and sub-blocks from the buffer.
+///
+/// A failing read makes the decoder return Error::ReadError, the end of the data
+/// Error::FileEnded. The error of the reader is kept and can be retrieved with take_error().
+/// If the reader also implements Seek, the source is Rewindable by seeking to the start
+/// and Seekable.
+pub struct ReadSource<'b, R: Read> {
+    reader: R,
+    buffer: &'b mut [u8],
+    start: usize,
+    end: usize,
+    /// offset of the next byte in the stream
+    position: usize,
+    error: Option<R::Error>,
+}
+
+impl<'b, R: Read> ReadSource<'b, R> {
+    /// The size of the buffer is the block size of the reads.
+    /// With an empty buffer every read fails with Error::ReadError.
+    pub fn new(reader: R, buffer: &'b mut [u8]) -> Self {
+        Self {
+            reader,
+            buffer,
+            start: 0,
+            end: 0,
+            position: 0,
+            error: None,
+        }
+    }
+
+    /// returns the error of the reader that failed the last read or seek
+    pub fn take_error(&mut self) -> Option<R::Error> {
+        self.error.take()
+    }
+
+    pub fn get_reader(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_reader(self) -> R {
+        self.reader
+    }
+
+    /// reads the next block if all bytes of the buffer have been taken
+    fn fill(&mut self) -> Result<(), Error> {
+        if self.start < self.end {
+            return Ok(());
+        }
+        if self.buffer.is_empty() {
+            return Err(Error::ReadError);
+        }
+
+        match self.reader.read(self.buffer) {
+            Ok(0) => Err(Error::FileEnded),
+            Ok(count) => {
+                self.start = 0;
+                self.end = count;
+                Ok(())
+            }
+            Err(error) => {
+                self.error = Some(error);
+                Err(Error::ReadError)
+            }
+        }
+    }
+}
+
+impl<'b, R: Read> DataSource for ReadSource<'b, R> {
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        self.fill()?;
+
+        let byte = self.buffer[self.start];
+        self.start += 1;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn take_bytes<F, E>(&mut self, mut len: usize, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+        E: From<Error>,
+    {
+        while len > 0 {
+            self.fill()?;
+
+            let count = len.min(self.end - self.start);
+            f(&self.buffer[self.start..self.start + count])?;
+            self.start += count;
+            self.position += count;
+            len -= count;
+        }
+        Ok(())
+    }
+}
+
+impl<'b, R: Read + Seek> Rewindable for ReadSource<'b, R> {
+    fn rewind(&mut self) -> Result<(), Error> {
+        self.seek(0).map_err(|_| Error::RewindError)
+    }
+}
+
+impl<'b, R: Read + Seek> Seekable for ReadSource<'b, R> {
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn seek(&mut self, offset: usize) -> Result<(), Error> {
+        self.start = 0;
+        self.end = 0;
+
+        match self.reader.seek(SeekFrom::Start(offset as u64)) {
+            Ok(_) => {
+                self.position = offset;
+                Ok(())
+            }
+            Err(error) => {
+                self.error = Some(error);
+                Err(Error::SeekError)
+            }
+        }
+    }
+}
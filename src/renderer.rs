@@ -1,14 +1,227 @@
 use crate::frame_decoder::ImageArea;
-use crate::gif_error::Error;
+use crate::pixel_format::{ColorTable, PixelFormat};
 
 pub trait ImageRenderer {
+    /// pixel format the color tables are converted into
+    type Format: PixelFormat;
+
+    /// Error of the display or bus. The decoders return it as Failure::Render in DecodeError,
+    /// so that it can be told apart from a broken file.
+    type Error;
+
+    fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<Self::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), Self::Error>;
+
+    fn flush_frame(&mut self) -> Result<(), Self::Error>;
+
+    /// Only called in compositing mode, before a frame with disposal method
+    /// RestoreToPrevious is drawn. The renderer should remember the contents of the
+    /// area, so that they can be put back by restore_area().
+    fn save_area(&mut self, _area: ImageArea) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Only called in compositing mode, before the next frame is drawn.
+    /// Disposes a RestoreToBackground frame by clearing its area,
+    /// unless background filling is enabled.
+    fn clear_area(&mut self, _area: ImageArea) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Only called when background filling is enabled. Fills the logical screen
+    /// before the first frame and disposes RestoreToBackground frames in compositing mode.
+    fn fill_area(
+        &mut self,
+        _area: ImageArea,
+        _color: <Self::Format as PixelFormat>::Pixel,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Only called in compositing mode, before the next frame is drawn.
+    /// Disposes a RestoreToPrevious frame by restoring the area saved by save_area().
+    fn restore_area(&mut self, _area: ImageArea) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A renderer call the decoder needs besides writing the image data,
+/// e.g. for disposing the previous frame. Lets the sync and async decoders
+/// share the compositing logic.
+#[derive(Clone, Copy)]
+pub(crate) enum RenderAction<P> {
+    Save(ImageArea),
+    Clear(ImageArea),
+    Fill(ImageArea, P),
+    Restore(ImageArea),
+}
+
+impl<P: Copy> RenderAction<P> {
+    pub(crate) fn apply<R>(self, renderer: &mut R) -> Result<(), R::Error>
+    where
+        R: ImageRenderer,
+        R::Format: PixelFormat<Pixel = P>,
+    {
+        match self {
+            RenderAction::Save(area) => renderer.save_area(area),
+            RenderAction::Clear(area) => renderer.clear_area(area),
+            RenderAction::Fill(area, color) => renderer.fill_area(area, color),
+            RenderAction::Restore(area) => renderer.restore_area(area),
+        }
+    }
+}
+
+/// Receives finished pixels instead of color indices,
+/// e.g. the changed region of the canvas of a Compositor.
+pub trait PixelRenderer {
+    type Format: PixelFormat;
+    type Error;
+
+    /// pixels holds the area row by row, area.width * area.height pixels
+    fn write_pixels(
+        &mut self,
+        area: ImageArea,
+        pixels: &[<Self::Format as PixelFormat>::Pixel],
+    ) -> Result<(), Self::Error>;
+
+    fn flush_frame(&mut self) -> Result<(), Self::Error>;
+}
+
+/// Alternative to ImageRenderer that receives the rows split into runs of opaque pixels,
+/// so that e.g. a display driver can set a window per span and never has to read back
+/// or skip transparent pixels. Wrap it in a SpanSplitter to pass it to a decoder.
+pub trait SpanRenderer {
+    /// pixel format the color tables are converted into
+    type Format: PixelFormat;
+    type Error;
+
+    /// Draws the opaque pixels starting at xpos in row ypos, indices is never empty
+    fn write_span(
+        &mut self,
+        xpos: u16,
+        ypos: u16,
+        indices: &[u8],
+        color_table: &ColorTable<Self::Format>,
+    ) -> Result<(), Self::Error>;
+
+    fn flush_frame(&mut self) -> Result<(), Self::Error>;
+
+    /// see ImageRenderer::save_area()
+    fn save_area(&mut self, _area: ImageArea) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// see ImageRenderer::clear_area()
+    fn clear_area(&mut self, _area: ImageArea) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// see ImageRenderer::fill_area()
+    fn fill_area(
+        &mut self,
+        _area: ImageArea,
+        _color: <Self::Format as PixelFormat>::Pixel,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// see ImageRenderer::restore_area()
+    fn restore_area(&mut self, _area: ImageArea) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// ImageRenderer that splits the rows at transparent pixels and passes the opaque spans
+/// to a SpanRenderer. Rows without transparency are passed as a single span.
+/// Pixels beyond the u16 coordinate range are dropped.
+pub struct SpanSplitter<R> {
+    renderer: R,
+}
+
+impl<R: SpanRenderer> SpanSplitter<R> {
+    pub fn new(renderer: R) -> Self {
+        Self { renderer }
+    }
+
+    pub fn get_renderer(&mut self) -> &mut R {
+        &mut self.renderer
+    }
+
+    pub fn into_renderer(self) -> R {
+        self.renderer
+    }
+}
+
+impl<R: SpanRenderer> ImageRenderer for SpanSplitter<R> {
+    type Format = R::Format;
+    type Error = R::Error;
+
     fn write_area(
         &mut self,
         area: ImageArea,
         buffer: &[u8],
-        color_table: &[u16; 256],
+        color_table: &ColorTable<Self::Format>,
         transparency_index: Option<u8>,
-    ) -> Result<(), Error>;
+    ) -> Result<(), R::Error> {
+        let width = area.width as usize;
+        if width == 0 {
+            return Ok(());
+        }
+        let is_opaque = |index: &u8| Some(*index) != transparency_index;
+        // the columns and rows that still have a coordinate
+        let visible_width = width.min(u16::MAX as usize - area.xpos as usize + 1);
+        let rows = buffer.chunks_exact(width).take(area.height as usize);
+
+        for (y, row) in (area.ypos..=u16::MAX).zip(rows) {
+            let row = &row[..visible_width];
+            let mut start = 0;
+
+            while start < visible_width {
+                let len = row[start..]
+                    .iter()
+                    .take_while(|index| is_opaque(index))
+                    .count();
+                if len > 0 {
+                    let xpos = area.xpos + start as u16;
+                    let span = &row[start..start + len];
+                    self.renderer.write_span(xpos, y, span, color_table)?;
+                }
+                start += len;
+                start += row[start..]
+                    .iter()
+                    .take_while(|index| !is_opaque(index))
+                    .count();
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), R::Error> {
+        self.renderer.flush_frame()
+    }
+
+    fn save_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        self.renderer.save_area(area)
+    }
+
+    fn clear_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        self.renderer.clear_area(area)
+    }
+
+    fn fill_area(
+        &mut self,
+        area: ImageArea,
+        color: <Self::Format as PixelFormat>::Pixel,
+    ) -> Result<(), R::Error> {
+        self.renderer.fill_area(area, color)
+    }
 
-    fn flush_frame(&mut self) -> Result<(), Error>;
+    fn restore_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        self.renderer.restore_area(area)
+    }
 }
@@ -0,0 +1,212 @@
+use crate::frame_decoder::ImageArea;
+use crate::pixel_format::{ColorTable, PixelFormat};
+use crate::renderer::ImageRenderer;
+
+/// Renderer that scales the frames by nearest neighbour before passing them on,
+/// e.g. to show a 120x120 animation on a 240x240 display. The areas of all renderer
+/// calls are scaled, so compositing and background filling keep working.
+///
+/// Destination pixel x shows source pixel x * denominator / numerator, the same for the
+/// rows. Rows are scaled one at a time into the buffer.
+/// A buffer that holds a whole scaled row passes the copies of an upscaled row in a
+/// single write_area() call as far as they fit, wider rows are passed in pieces.
+/// Without a buffer every scaled pixel is passed on its own.
+pub struct Scaler<'b, R> {
+    renderer: R,
+    buffer: &'b mut [u8],
+    numerator: u64,
+    denominator: u64,
+}
+
+impl<'b, R: ImageRenderer> Scaler<'b, R> {
+    /// scales up by an integer factor
+    pub fn new(renderer: R, buffer: &'b mut [u8], factor: u16) -> Self {
+        Self::with_ratio(renderer, buffer, factor, 1)
+    }
+
+    /// Scales by numerator / denominator, which may also shrink the frames.
+    /// A zero is treated as 1.
+    pub fn with_ratio(renderer: R, buffer: &'b mut [u8], numerator: u16, denominator: u16) -> Self {
+        Self {
+            renderer,
+            buffer,
+            numerator: numerator.max(1) as u64,
+            denominator: denominator.max(1) as u64,
+        }
+    }
+
+    pub fn get_renderer(&mut self) -> &mut R {
+        &mut self.renderer
+    }
+
+    pub fn into_renderer(self) -> R {
+        self.renderer
+    }
+
+    /// The area on the destination that shows the source area, e.g. to size the display
+    /// window for the logical screen. Coordinates beyond u16::MAX are cut off.
+    pub fn scale_area(&self, area: ImageArea) -> ImageArea {
+        let (xpos, x_end) = self.scale_range(area.xpos, area.width);
+        let (ypos, y_end) = self.scale_range(area.ypos, area.height);
+
+        ImageArea {
+            xpos,
+            ypos,
+            width: x_end - xpos,
+            height: y_end - ypos,
+        }
+    }
+
+    /// the destination pixels whose source pixel is in start..start + len,
+    /// computed in u64 as positions up to 2 * u16::MAX are scaled by up to u16::MAX
+    fn scale_range(&self, start: u16, len: u16) -> (u16, u16) {
+        let scale = |position: u64| {
+            let scaled = (position * self.numerator).div_ceil(self.denominator);
+            scaled.min(u16::MAX as u64) as u16
+        };
+        (scale(start as u64), scale(start as u64 + len as u64))
+    }
+
+    /// passes a source row to the renderer, as often as it is repeated on the destination
+    fn write_row(
+        &mut self,
+        area: ImageArea,
+        row: &[u8],
+        color_table: &ColorTable<R::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), R::Error> {
+        let (x_start, x_end) = self.scale_range(area.xpos, area.width);
+        let (y_start, y_end) = self.scale_range(area.ypos, 1);
+        let dest_width = (x_end - x_start) as usize;
+        if dest_width == 0 || y_start == y_end {
+            return Ok(());
+        }
+        if self.buffer.is_empty() {
+            return self.write_pixels(area, row, color_table, transparency_index);
+        }
+
+        let piece_width = dest_width.min(self.buffer.len());
+        // a single area holds at most u16::MAX rows
+        let rows_per_call = match piece_width == dest_width {
+            true => (self.buffer.len() / dest_width).clamp(1, u16::MAX as usize),
+            false => 1,
+        };
+
+        for piece_start in (x_start..x_end).step_by(piece_width) {
+            let piece_end = x_end.min(piece_start.saturating_add(piece_width as u16));
+            let width = (piece_end - piece_start) as usize;
+
+            for (x, pixel) in (piece_start..piece_end).zip(self.buffer.iter_mut()) {
+                let source_x = x as u64 * self.denominator / self.numerator;
+                *pixel = row[source_x as usize - area.xpos as usize];
+            }
+
+            let mut y = y_start;
+            while y < y_end {
+                let height = (y_end - y).min(rows_per_call as u16);
+                for copy in 1..height as usize {
+                    self.buffer.copy_within(..width, copy * width);
+                }
+
+                let dest_area = ImageArea {
+                    xpos: piece_start,
+                    ypos: y,
+                    width: width as u16,
+                    height,
+                };
+                let pixels = &self.buffer[..width * height as usize];
+                self.renderer
+                    .write_area(dest_area, pixels, color_table, transparency_index)?;
+                y += height;
+            }
+        }
+        Ok(())
+    }
+
+    /// passes a source row pixel by pixel, a single pixel needs no buffer
+    fn write_pixels(
+        &mut self,
+        area: ImageArea,
+        row: &[u8],
+        color_table: &ColorTable<R::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), R::Error> {
+        let (x_start, x_end) = self.scale_range(area.xpos, area.width);
+        let (y_start, y_end) = self.scale_range(area.ypos, 1);
+
+        for ypos in y_start..y_end {
+            for xpos in x_start..x_end {
+                let source_x = xpos as u64 * self.denominator / self.numerator;
+                let index = source_x as usize - area.xpos as usize;
+                let dest_area = ImageArea {
+                    xpos,
+                    ypos,
+                    width: 1,
+                    height: 1,
+                };
+                self.renderer.write_area(
+                    dest_area,
+                    &row[index..index + 1],
+                    color_table,
+                    transparency_index,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'b, R: ImageRenderer> ImageRenderer for Scaler<'b, R> {
+    type Format = R::Format;
+    type Error = R::Error;
+
+    fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<Self::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), R::Error> {
+        let width = area.width as usize;
+        if width == 0 {
+            return Ok(());
+        }
+
+        // rows beyond the coordinate range are dropped
+        let rows = buffer.chunks_exact(width).take(area.height as usize);
+
+        for (y, row) in (area.ypos..=u16::MAX).zip(rows) {
+            let row_area = ImageArea {
+                ypos: y,
+                height: 1,
+                ..area
+            };
+            self.write_row(row_area, row, color_table, transparency_index)?;
+        }
+        Ok(())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), R::Error> {
+        self.renderer.flush_frame()
+    }
+
+    fn save_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        self.renderer.save_area(self.scale_area(area))
+    }
+
+    fn clear_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        self.renderer.clear_area(self.scale_area(area))
+    }
+
+    fn fill_area(
+        &mut self,
+        area: ImageArea,
+        color: <Self::Format as PixelFormat>::Pixel,
+    ) -> Result<(), R::Error> {
+        self.renderer.fill_area(self.scale_area(area), color)
+    }
+
+    fn restore_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        self.renderer.restore_area(self.scale_area(area))
+    }
+}
@@ -0,0 +1,342 @@
+use crate::frame_decoder::ImageArea;
+use crate::pixel_format::{ColorTable, PixelFormat};
+use crate::renderer::ImageRenderer;
+
+/// Clockwise rotation of the output of a Transform
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+/// Renderer that rotates and mirrors the frames before passing them on,
+/// for displays that are mounted turned. The logical screen is flipped first and then
+/// rotated clockwise, the areas of all renderer calls are moved along.
+///
+/// Without rotation a vertical flip only moves the rows and needs no buffer.
+/// A horizontal flip and 180° reverse the rows in the buffer, which should hold a row of
+/// the logical screen. 90° and 270° turn the rows of each write_area() call into columns,
+/// a buffer of the size of the output buffer of the decoder keeps the calls as large as
+/// they were. Smaller buffers split the calls into narrower bands, interlaced frames are
+/// always passed one column at a time. Without a buffer every pixel is passed on its own.
+/// Pixels outside of the logical screen are dropped.
+pub struct Transform<'b, R> {
+    renderer: R,
+    buffer: &'b mut [u8],
+    orientation: Orientation,
+}
+
+/// maps points of the logical screen to the display
+#[derive(Clone, Copy)]
+struct Orientation {
+    width: u16,
+    height: u16,
+    rotation: Rotation,
+    flip_horizontal: bool,
+    flip_vertical: bool,
+}
+
+impl Orientation {
+    fn transposed(&self) -> bool {
+        matches!(self.rotation, Rotation::Deg90 | Rotation::Deg270)
+    }
+
+    /// whether the pixels of a row end up in reverse order, without rotation by 90 or 270
+    fn reverses_rows(&self) -> bool {
+        self.flip_horizontal != (self.rotation == Rotation::Deg180)
+    }
+
+    fn flip(&self, x: u16, y: u16) -> (u16, u16) {
+        let x = match self.flip_horizontal {
+            true => self.width - 1 - x,
+            false => x,
+        };
+        let y = match self.flip_vertical {
+            true => self.height - 1 - y,
+            false => y,
+        };
+        (x, y)
+    }
+
+    /// the display position of a point of the logical screen
+    fn display_point(&self, x: u16, y: u16) -> (u16, u16) {
+        let (x, y) = self.flip(x, y);
+        let (width, height) = (self.width, self.height);
+
+        match self.rotation {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (height - 1 - y, x),
+            Rotation::Deg180 => (width - 1 - x, height - 1 - y),
+            Rotation::Deg270 => (y, width - 1 - x),
+        }
+    }
+
+    /// the point of the logical screen shown at a display position
+    fn screen_point(&self, x: u16, y: u16) -> (u16, u16) {
+        let (width, height) = (self.width, self.height);
+
+        let (x, y) = match self.rotation {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (y, height - 1 - x),
+            Rotation::Deg180 => (width - 1 - x, height - 1 - y),
+            Rotation::Deg270 => (width - 1 - y, x),
+        };
+        self.flip(x, y)
+    }
+
+    /// the part of area that is on the logical screen
+    fn clip(&self, area: ImageArea) -> Option<ImageArea> {
+        let x_end = area.xpos.saturating_add(area.width).min(self.width);
+        let y_end = area.ypos.saturating_add(area.height).min(self.height);
+
+        if area.xpos >= x_end || area.ypos >= y_end {
+            return None;
+        }
+        Some(ImageArea {
+            xpos: area.xpos,
+            ypos: area.ypos,
+            width: x_end - area.xpos,
+            height: y_end - area.ypos,
+        })
+    }
+
+    fn display_area(&self, area: ImageArea) -> Option<ImageArea> {
+        let area = self.clip(area)?;
+        let (x0, y0) = self.display_point(area.xpos, area.ypos);
+        let (x1, y1) = self.display_point(area.xpos + area.width - 1, area.ypos + area.height - 1);
+
+        Some(ImageArea {
+            xpos: x0.min(x1),
+            ypos: y0.min(y1),
+            width: x0.abs_diff(x1) + 1,
+            height: y0.abs_diff(y1) + 1,
+        })
+    }
+}
+
+impl<'b, R: ImageRenderer> Transform<'b, R> {
+    /// Width and height are the size of the logical screen of the file.
+    /// The buffer may be empty if the output is only flipped vertically.
+    pub fn new(renderer: R, buffer: &'b mut [u8], width: u16, height: u16) -> Self {
+        Self {
+            renderer,
+            buffer,
+            orientation: Orientation {
+                width,
+                height,
+                rotation: Rotation::Deg0,
+                flip_horizontal: false,
+                flip_vertical: false,
+            },
+        }
+    }
+
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.orientation.rotation = rotation;
+    }
+
+    /// mirrors the logical screen, before it is rotated
+    pub fn set_flip(&mut self, horizontal: bool, vertical: bool) {
+        self.orientation.flip_horizontal = horizontal;
+        self.orientation.flip_vertical = vertical;
+    }
+
+    pub fn get_renderer(&mut self) -> &mut R {
+        &mut self.renderer
+    }
+
+    pub fn into_renderer(self) -> R {
+        self.renderer
+    }
+
+    /// width and height of the logical screen on the display
+    pub fn display_size(&self) -> (u16, u16) {
+        match self.orientation.transposed() {
+            true => (self.orientation.height, self.orientation.width),
+            false => (self.orientation.width, self.orientation.height),
+        }
+    }
+
+    /// where an area of the logical screen ends up on the display,
+    /// None if it is outside of the logical screen
+    pub fn display_area(&self, area: ImageArea) -> Option<ImageArea> {
+        self.orientation.display_area(area)
+    }
+
+    /// rotates or reverses the visible part of an area in the buffer, band by band
+    fn write_buffered(
+        &mut self,
+        area: ImageArea,
+        visible: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<R::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), R::Error> {
+        let orientation = self.orientation;
+        if self.buffer.is_empty() {
+            return self.write_pixels(area, visible, buffer, color_table, transparency_index);
+        }
+        let piece_width = visible
+            .width
+            .min(self.buffer.len().min(u16::MAX as usize) as u16);
+        let band_height = (self.buffer.len() / piece_width as usize).max(1);
+        let band_height = visible
+            .height
+            .min(band_height.min(u16::MAX as usize) as u16);
+        let x_end = visible.xpos + visible.width;
+        let y_end = visible.ypos + visible.height;
+
+        for ypos in (visible.ypos..y_end).step_by(band_height as usize) {
+            for xpos in (visible.xpos..x_end).step_by(piece_width as usize) {
+                let part = ImageArea {
+                    xpos,
+                    ypos,
+                    width: piece_width.min(x_end - xpos),
+                    height: band_height.min(y_end - ypos),
+                };
+                let Some(dest) = orientation.display_area(part) else {
+                    continue;
+                };
+
+                let rows = dest.ypos..dest.ypos + dest.height;
+                let points =
+                    rows.flat_map(|y| (dest.xpos..dest.xpos + dest.width).map(move |x| (x, y)));
+                for ((x, y), pixel) in points.zip(self.buffer.iter_mut()) {
+                    let (x, y) = orientation.screen_point(x, y);
+                    let index =
+                        (y - area.ypos) as usize * area.width as usize + (x - area.xpos) as usize;
+                    *pixel = buffer[index];
+                }
+
+                let len = dest.width as usize * dest.height as usize;
+                self.renderer.write_area(
+                    dest,
+                    &self.buffer[..len],
+                    color_table,
+                    transparency_index,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// passes the visible part of an area on pixel by pixel, a single pixel needs no buffer
+    fn write_pixels(
+        &mut self,
+        area: ImageArea,
+        visible: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<R::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), R::Error> {
+        let orientation = self.orientation;
+
+        for y in visible.ypos..visible.ypos + visible.height {
+            for x in visible.xpos..visible.xpos + visible.width {
+                let (xpos, ypos) = orientation.display_point(x, y);
+                let dest = ImageArea {
+                    xpos,
+                    ypos,
+                    width: 1,
+                    height: 1,
+                };
+                let index =
+                    (y - area.ypos) as usize * area.width as usize + (x - area.xpos) as usize;
+                self.renderer.write_area(
+                    dest,
+                    &buffer[index..index + 1],
+                    color_table,
+                    transparency_index,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'b, R: ImageRenderer> ImageRenderer for Transform<'b, R> {
+    type Format = R::Format;
+    type Error = R::Error;
+
+    fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<Self::Format>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), R::Error> {
+        let orientation = self.orientation;
+        if orientation.rotation == Rotation::Deg0
+            && !orientation.flip_horizontal
+            && !orientation.flip_vertical
+        {
+            return self
+                .renderer
+                .write_area(area, buffer, color_table, transparency_index);
+        }
+        let Some(visible) = orientation.clip(area) else {
+            return Ok(());
+        };
+
+        if orientation.transposed() || orientation.reverses_rows() {
+            return self.write_buffered(area, visible, buffer, color_table, transparency_index);
+        }
+
+        // the rows keep their pixel order, only their position changes
+        for y in visible.ypos..visible.ypos + visible.height {
+            let row = ImageArea {
+                ypos: y,
+                height: 1,
+                ..visible
+            };
+            let Some(dest) = orientation.display_area(row) else {
+                continue;
+            };
+            let start = (y - area.ypos) as usize * area.width as usize
+                + (visible.xpos - area.xpos) as usize;
+            let pixels = &buffer[start..start + visible.width as usize];
+            self.renderer
+                .write_area(dest, pixels, color_table, transparency_index)?;
+        }
+        Ok(())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), R::Error> {
+        self.renderer.flush_frame()
+    }
+
+    fn save_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        match self.display_area(area) {
+            Some(area) => self.renderer.save_area(area),
+            None => Ok(()),
+        }
+    }
+
+    fn clear_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        match self.display_area(area) {
+            Some(area) => self.renderer.clear_area(area),
+            None => Ok(()),
+        }
+    }
+
+    fn fill_area(
+        &mut self,
+        area: ImageArea,
+        color: <Self::Format as PixelFormat>::Pixel,
+    ) -> Result<(), R::Error> {
+        match self.display_area(area) {
+            Some(area) => self.renderer.fill_area(area, color),
+            None => Ok(()),
+        }
+    }
+
+    fn restore_area(&mut self, area: ImageArea) -> Result<(), R::Error> {
+        match self.display_area(area) {
+            Some(area) => self.renderer.restore_area(area),
+            None => Ok(()),
+        }
+    }
+}
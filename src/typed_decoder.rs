@@ -0,0 +1,208 @@
+use crate::data_source::DataSource;
+use crate::gif_decoder::{GifDecoder, GifFileMetadata, Rewindable};
+use crate::gif_error::{DecodeError, Error};
+use crate::pixel_format::PixelFormat;
+use crate::renderer::ImageRenderer;
+use core::marker::PhantomData;
+
+/// state of a TypedGifDecoder before the file metadata has been parsed
+pub struct Header;
+/// state of a TypedGifDecoder between frames, the next block is a frame or the trailer
+pub struct FrameReady;
+/// state of a TypedGifDecoder after the frame metadata, the image data follows
+pub struct ImageData;
+/// state of a TypedGifDecoder after the last frame of the file
+pub struct Trailer;
+
+/// Wrapper around GifDecoder that enforces the order of the calls at compile time.
+/// Every step consumes the decoder and returns it in the state that follows,
+/// so only the methods that are valid for the current position in the file exist:
+///
+/// Header --parse_gif_metadata()--> FrameReady --next_frame()--> ImageData or Trailer,
+/// ImageData --decode_frame_image()--> FrameReady
+///
+/// The states are zero sized, the wrapper has the same size as the GifDecoder.
+pub struct TypedGifDecoder<'a, DS, R, S, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+where
+    R: ImageRenderer,
+{
+    decoder: GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+    state: PhantomData<S>,
+}
+
+/// Result of TypedGifDecoder::next_frame()
+pub enum NextFrame<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+where
+    R: ImageRenderer,
+{
+    /// the metadata of the next frame has been parsed, its image data follows
+    Frame(TypedGifDecoder<'a, DS, R, ImageData, OUT_BUF_LEN, REVERSE_BUF_LEN>),
+    /// there is no frame left
+    Ended(TypedGifDecoder<'a, DS, R, Trailer, OUT_BUF_LEN, REVERSE_BUF_LEN>),
+}
+
+impl<'a, DS, R, S, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    TypedGifDecoder<'a, DS, R, S, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource,
+    R: ImageRenderer,
+{
+    fn into_state<T>(self) -> TypedGifDecoder<'a, DS, R, T, OUT_BUF_LEN, REVERSE_BUF_LEN> {
+        TypedGifDecoder {
+            decoder: self.decoder,
+            state: PhantomData,
+        }
+    }
+
+    pub fn get_data_source(&mut self) -> &mut DS {
+        self.decoder.get_data_source()
+    }
+
+    /// returns the wrapped decoder, its methods are then again unchecked
+    pub fn into_inner(self) -> GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN> {
+        self.decoder
+    }
+}
+
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    TypedGifDecoder<'a, DS, R, Header, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource,
+    R: ImageRenderer,
+{
+    /// takes a freshly constructed decoder, the data source has to be at the start of the file
+    pub fn new(decoder: GifDecoder<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>) -> Self {
+        TypedGifDecoder {
+            decoder,
+            state: PhantomData,
+        }
+    }
+
+    /// Enables honouring the disposal methods of frames, see ImageRenderer::clear_area()
+    pub fn set_compositing(&mut self, enabled: bool) {
+        self.decoder.set_compositing(enabled);
+    }
+
+    /// Enables filling with the background color of the file, see ImageRenderer::fill_area()
+    pub fn set_fill_background(&mut self, enabled: bool) {
+        self.decoder.set_fill_background(enabled);
+    }
+
+    /// Parses and consumes the initial metadata section of a GIF file
+    pub fn parse_gif_metadata(
+        mut self,
+    ) -> Result<
+        TypedGifDecoder<'a, DS, R, FrameReady, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+        DecodeError<R::Error>,
+    > {
+        self.decoder.parse_gif_metadata()?;
+        Ok(self.into_state())
+    }
+}
+
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    TypedGifDecoder<'a, DS, R, FrameReady, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource,
+    R: ImageRenderer,
+{
+    pub fn get_gif_metadata(&self) -> Option<&GifFileMetadata<<R::Format as PixelFormat>::Pixel>> {
+        self.decoder.get_gif_metadata()
+    }
+
+    /// Parses and consumes the metadata section of the next frame,
+    /// including all GIF extensions up until the actual image data.
+    pub fn next_frame(
+        mut self,
+    ) -> Result<NextFrame<'a, DS, R, OUT_BUF_LEN, REVERSE_BUF_LEN>, DecodeError<R::Error>> {
+        match self.decoder.parse_frame_metadata() {
+            Ok(()) => Ok(NextFrame::Frame(self.into_state())),
+            Err(error) if error.error() == Error::GifEnded => {
+                Ok(NextFrame::Ended(self.into_state()))
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    TypedGifDecoder<'a, DS, R, ImageData, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource,
+    R: ImageRenderer,
+{
+    pub fn get_gif_metadata(&self) -> Option<&GifFileMetadata<<R::Format as PixelFormat>::Pixel>> {
+        self.decoder.get_gif_metadata()
+    }
+
+    pub fn get_current_frame_delay_ms(&self) -> Option<u32> {
+        self.decoder.get_current_frame_delay_ms()
+    }
+
+    /// Decodes and consumes the image data of the frame, see GifDecoder::decode_frame_image()
+    pub fn decode_frame_image(
+        mut self,
+    ) -> Result<
+        TypedGifDecoder<'a, DS, R, FrameReady, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+        DecodeError<R::Error>,
+    > {
+        self.decoder.decode_frame_image()?;
+        Ok(self.into_state())
+    }
+
+    /// Like decode_frame_image(), but draws the frame with a different renderer
+    pub fn decode_frame_image_into<T>(
+        mut self,
+        renderer: &mut T,
+    ) -> Result<
+        TypedGifDecoder<'a, DS, R, FrameReady, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+        DecodeError<T::Error>,
+    >
+    where
+        T: ImageRenderer<Format = R::Format>,
+    {
+        self.decoder.decode_frame_image_into(renderer)?;
+        Ok(self.into_state())
+    }
+
+    /// Consumes the image data of the frame without decoding it,
+    /// see GifDecoder::skip_frame_image()
+    pub fn skip_frame_image(
+        mut self,
+    ) -> Result<
+        TypedGifDecoder<'a, DS, R, FrameReady, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+        DecodeError<R::Error>,
+    > {
+        self.decoder.skip_frame_image()?;
+        Ok(self.into_state())
+    }
+}
+
+impl<'a, DS, R, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    TypedGifDecoder<'a, DS, R, Trailer, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource,
+    R: ImageRenderer,
+{
+    pub fn get_gif_metadata(&self) -> Option<&GifFileMetadata<<R::Format as PixelFormat>::Pixel>> {
+        self.decoder.get_gif_metadata()
+    }
+}
+
+impl<'a, DS, R, S, const OUT_BUF_LEN: usize, const REVERSE_BUF_LEN: usize>
+    TypedGifDecoder<'a, DS, R, S, OUT_BUF_LEN, REVERSE_BUF_LEN>
+where
+    DS: DataSource + Rewindable,
+    R: ImageRenderer,
+{
+    /// Goes back to the start of the file from any state, e.g. to loop the animation
+    pub fn rewind(
+        mut self,
+    ) -> Result<
+        TypedGifDecoder<'a, DS, R, Header, OUT_BUF_LEN, REVERSE_BUF_LEN>,
+        DecodeError<R::Error>,
+    > {
+        self.decoder.rewind()?;
+        Ok(self.into_state())
+    }
+}
@@ -1,33 +1,80 @@
+use embedded_gif::compositor::Compositor;
+use embedded_gif::data_source::{DataSource, Seekable, SliceSource};
 use embedded_gif::frame_decoder::LzwEntry;
-use embedded_gif::gif_decoder::{OUT_BUF_LEN, REVERSE_BUF_LEN};
-use embedded_gif::gif_error::Error;
-use embedded_gif::renderer::ImageRenderer;
+use embedded_gif::frame_decoder::{DisposalMethod, FrameInfo};
+use embedded_gif::frame_index::FrameIndexEntry;
+use embedded_gif::gif_decoder::{GifFileMetadata, GifVersion, LoopCount, Rewindable};
+use embedded_gif::gif_error::{DecodeError, Error, Failure};
+use embedded_gif::pixel_format::{
+    Bgr565, ColorTable, Gray8, PixelFormat, Rgb565, Rgb565Be, Rgb565Le, Rgb666, Rgb888,
+};
+use embedded_gif::player::{GifPlayer, PlayerStatus};
+use embedded_gif::push_decoder::{Progress, PushDecoder};
+use embedded_gif::renderer::{ImageRenderer, PixelRenderer, SpanRenderer, SpanSplitter};
+use embedded_gif::scaler::Scaler;
+use embedded_gif::transform::{Rotation, Transform};
+use embedded_gif::typed_decoder::{NextFrame, TypedGifDecoder};
 use embedded_gif::{frame_decoder::ImageArea, gif_decoder::GifDecoder};
-use image::{ImageBuffer, Rgba};
-use std::fs::create_dir;
+use image::codecs::gif;
+use image::{AnimationDecoder, ImageBuffer, Rgba};
+use std::fs::create_dir_all;
 use std::fs::read;
 use std::fs::remove_dir_all;
 
 const SCREEN_SIZE: usize = 240;
+const REVERSE_BUF_LEN: usize = 512;
+const OUT_BUF_LEN: usize = SCREEN_SIZE * 20; // 20 lines
+
+type Screen = ImageBuffer<Rgba<u8>, Vec<u8>>;
 
 struct TestRenderer {
-    screen: ImageBuffer<Rgba<u8>, Vec<u8>>,
-    current_frame: usize,
+    screen: Screen,
+    saved_screen: Screen,
+    frames: Vec<Screen>,
+    output_dir: String,
+    compositing: bool,
+    fill_background: bool,
 }
 
 impl TestRenderer {
-    fn new() -> Self {
+    fn new(name: &str) -> Self {
+        let output_dir = format!("./tests/frames/{}", name);
+        let _ = remove_dir_all(&output_dir);
+        create_dir_all(&output_dir).unwrap();
+
         let mut screen = ImageBuffer::new(SCREEN_SIZE as u32, SCREEN_SIZE as u32);
         screen.fill(0);
 
         Self {
+            saved_screen: screen.clone(),
             screen,
-            current_frame: 0,
+            frames: Vec::new(),
+            output_dir,
+            compositing: false,
+            fill_background: false,
+        }
+    }
+
+    /// keeps the screen between frames and skips transparent pixels
+    fn new_compositing(name: &str) -> Self {
+        Self {
+            compositing: true,
+            ..Self::new(name)
         }
     }
+
+    fn screen_area(area: ImageArea) -> impl Iterator<Item = (u32, u32)> {
+        let xs = area.xpos as u32..(area.xpos + area.width).min(SCREEN_SIZE as u16) as u32;
+        let ys = area.ypos as u32..(area.ypos + area.height).min(SCREEN_SIZE as u16) as u32;
+
+        ys.flat_map(move |y| xs.clone().map(move |x| (x, y)))
+    }
 }
 
 impl ImageRenderer for TestRenderer {
+    type Format = Rgb565;
+    type Error = Error;
+
     fn write_area(
         &mut self,
         area: ImageArea,
@@ -41,17 +88,17 @@ impl ImageRenderer for TestRenderer {
             for x in area.xpos..(area.xpos + area.width) {
                 let color_index = buffer[buf_index];
                 buf_index += 1;
-                let color_16 = color_table[color_index as usize];
-
-                let r = ((color_16 & 0b1111100000000000) >> 8) as u8;
-                let g = ((color_16 & 0b0000011111100000) >> 3) as u8;
-                let b = ((color_16 & 0b0000000000011111) << 3) as u8;
+                let Rgba([r, g, b, _]) = rgba_from_565(color_table[color_index as usize]);
 
                 let a = match transparency_index {
                     Some(ti) if ti == color_index => 0x00,
                     _ => 0xFF,
                 };
 
+                if self.compositing && a == 0x00 {
+                    continue;
+                }
+
                 if (x as usize) < SCREEN_SIZE && (y as usize) < SCREEN_SIZE {
                     let pixel = Rgba([r, g, b, a]);
 
@@ -63,16 +110,87 @@ impl ImageRenderer for TestRenderer {
     }
 
     fn flush_frame(&mut self) -> Result<(), Error> {
-        self.screen
-            .save(format!("./tests/frames/frame_{}.png", self.current_frame))
-            .or(Err(Error::RenderError))?;
+        let path = format!("{}/frame_{}.png", self.output_dir, self.frames.len());
+        self.screen.save(path).or(Err(Error::RenderError))?;
+
+        self.frames.push(self.screen.clone());
+        if !self.compositing {
+            self.screen.fill(0);
+        }
+        Ok(())
+    }
+
+    fn save_area(&mut self, area: ImageArea) -> Result<(), Error> {
+        for (x, y) in Self::screen_area(area) {
+            self.saved_screen
+                .put_pixel(x, y, *self.screen.get_pixel(x, y));
+        }
+        Ok(())
+    }
+
+    fn fill_area(&mut self, area: ImageArea, color: u16) -> Result<(), Error> {
+        for (x, y) in Self::screen_area(area) {
+            self.screen.put_pixel(x, y, rgba_from_565(color));
+        }
+        Ok(())
+    }
+
+    fn clear_area(&mut self, area: ImageArea) -> Result<(), Error> {
+        for (x, y) in Self::screen_area(area) {
+            self.screen.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+        }
+        Ok(())
+    }
+
+    fn restore_area(&mut self, area: ImageArea) -> Result<(), Error> {
+        for (x, y) in Self::screen_area(area) {
+            self.screen
+                .put_pixel(x, y, *self.saved_screen.get_pixel(x, y));
+        }
+        Ok(())
+    }
+}
+
+/// data source that can be played more than once
+struct RewindableBytes {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl RewindableBytes {
+    fn new(path: &str) -> Self {
+        Self {
+            bytes: read(path).unwrap(),
+            position: 0,
+        }
+    }
+}
+
+impl Iterator for RewindableBytes {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.bytes.get(self.position).copied();
+        self.position += 1;
+        byte
+    }
+}
 
-        self.current_frame += 1;
-        self.screen.fill(0);
+impl Rewindable for RewindableBytes {
+    fn rewind(&mut self) -> Result<(), Error> {
+        self.position = 0;
         Ok(())
     }
 }
 
+fn rgba_from_565(color: u16) -> Rgba<u8> {
+    let r = ((color & 0b1111100000000000) >> 8) as u8;
+    let g = ((color & 0b0000011111100000) >> 3) as u8;
+    let b = ((color & 0b0000000000011111) << 3) as u8;
+
+    Rgba([r, g, b, 0xFF])
+}
+
 fn vec_to_boxed_array<T: Copy, const N: usize>(val: T) -> Box<[T; N]> {
     let boxed_slice = vec![val; N].into_boxed_slice();
 
@@ -81,39 +199,1546 @@ fn vec_to_boxed_array<T: Copy, const N: usize>(val: T) -> Box<[T; N]> {
     unsafe { Box::from_raw(ptr) }
 }
 
+/// the buffers of a decoder, boxed to keep them off the stack
+struct Buffers<P = u16, const OUT: usize = OUT_BUF_LEN> {
+    global_color_table: Box<[P; 256]>,
+    local_color_table: Box<[P; 256]>,
+    lzw_table: Box<[LzwEntry; 4096]>,
+    reverse_buffer: Box<[u8; REVERSE_BUF_LEN]>,
+    output_buffer: Box<[u8; OUT]>,
+}
+
+/// buffers of the default sizes for RGB565
+fn new_buffers() -> Buffers {
+    Buffers::new()
+}
+
+impl<P: Copy + Default, const OUT: usize> Buffers<P, OUT> {
+    fn new() -> Self {
+        Self {
+            global_color_table: vec_to_boxed_array(P::default()),
+            local_color_table: vec_to_boxed_array(P::default()),
+            lzw_table: vec_to_boxed_array(LzwEntry::default()),
+            reverse_buffer: vec_to_boxed_array(0),
+            output_buffer: vec_to_boxed_array(0),
+        }
+    }
+
+    fn decoder<'a, DS, R>(
+        &'a mut self,
+        data_source: DS,
+        renderer: &'a mut R,
+    ) -> GifDecoder<'a, DS, R, OUT, REVERSE_BUF_LEN>
+    where
+        DS: DataSource,
+        R: ImageRenderer,
+        R::Format: PixelFormat<Pixel = P>,
+    {
+        GifDecoder::new(
+            data_source,
+            renderer,
+            &mut self.global_color_table,
+            &mut self.local_color_table,
+            &mut self.lzw_table,
+            &mut self.reverse_buffer,
+            &mut self.output_buffer,
+        )
+    }
+
+    fn push_decoder<'a, R>(
+        &'a mut self,
+        renderer: &'a mut R,
+    ) -> PushDecoder<'a, R, OUT, REVERSE_BUF_LEN>
+    where
+        R: ImageRenderer,
+        R::Format: PixelFormat<Pixel = P>,
+    {
+        PushDecoder::new(
+            renderer,
+            &mut self.global_color_table,
+            &mut self.local_color_table,
+            &mut self.lzw_table,
+            &mut self.reverse_buffer,
+            &mut self.output_buffer,
+        )
+    }
+
+    #[cfg(feature = "async")]
+    fn async_decoder<'a, DS, R>(
+        &'a mut self,
+        data_source: DS,
+        read_buffer: &'a mut [u8],
+        renderer: &'a mut R,
+    ) -> embedded_gif::async_decoder::AsyncGifDecoder<'a, DS, R, OUT, REVERSE_BUF_LEN>
+    where
+        DS: embedded_io_async::Read,
+        R: embedded_gif::async_decoder::AsyncImageRenderer,
+        R::Format: PixelFormat<Pixel = P>,
+    {
+        embedded_gif::async_decoder::AsyncGifDecoder::new(
+            data_source,
+            read_buffer,
+            renderer,
+            &mut self.global_color_table,
+            &mut self.local_color_table,
+            &mut self.lzw_table,
+            &mut self.reverse_buffer,
+            &mut self.output_buffer,
+        )
+    }
+}
+
+fn decode_file(path: &str, renderer: &mut TestRenderer) -> GifFileMetadata<u16> {
+    let compositing = renderer.compositing;
+    let fill_background = renderer.fill_background;
+
+    decode_with(path, renderer, compositing, fill_background)
+}
+
+fn decode_with<R>(
+    path: &str,
+    renderer: &mut R,
+    compositing: bool,
+    fill_background: bool,
+) -> GifFileMetadata<u16>
+where
+    R: ImageRenderer<Format = Rgb565>,
+    R::Error: core::fmt::Debug,
+{
+    let bytes = read(path).unwrap();
+
+    let mut data_source = bytes.into_iter();
+
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.decoder(&mut data_source, renderer);
+    decoder.set_compositing(compositing);
+    decoder.set_fill_background(fill_background);
+
+    decoder.parse_gif_metadata().unwrap();
+
+    while let Some(frame) = decoder.next_frame() {
+        frame.unwrap();
+        decoder.decode_frame_image().unwrap();
+    }
+    decoder.get_gif_metadata().unwrap().clone()
+}
+
+fn assert_frames_match(actual: &[Screen], expected: &[Screen]) {
+    assert_eq!(actual.len(), expected.len());
+
+    for (i, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+        for (x, y, pixel) in expected.enumerate_pixels() {
+            assert_eq!(actual.get_pixel(x, y), pixel, "frame {} at {} {}", i, x, y);
+        }
+    }
+}
+
+/// decodes a file with the image crate
+fn reference_frames_rgba(path: &str) -> Vec<Screen> {
+    let file = std::fs::File::open(path).unwrap();
+    let decoder = gif::GifDecoder::new(file).unwrap();
+
+    let frames = decoder.into_frames().collect_frames().unwrap();
+
+    frames
+        .into_iter()
+        .map(|frame| frame.into_buffer())
+        .collect()
+}
+
+/// decodes a file with the image crate, reduced to the precision of RGB565
+fn reference_frames(path: &str) -> Vec<Screen> {
+    reference_frames_rgba(path)
+        .into_iter()
+        .map(|mut buffer| {
+            for pixel in buffer.pixels_mut() {
+                let [r, g, b, a] = pixel.0;
+                pixel.0 = [r & 0xF8, g & 0xFC, b & 0xF8, a];
+            }
+            buffer
+        })
+        .collect()
+}
+
 #[test]
 fn gif_test() {
-    let _ = remove_dir_all("./tests/frames");
-    create_dir("./tests/frames").unwrap();
+    let mut renderer = TestRenderer::new("gif_test");
 
-    let bytes = read("./tests/gifs/test_large.gif").unwrap();
+    let metadata = decode_file("./tests/gifs/test_large.gif", &mut renderer);
 
-    let mut data_source = bytes.into_iter();
-    let mut renderer = TestRenderer::new();
+    assert_eq!(metadata.version, GifVersion::Gif89a);
+}
 
-    let mut buf_a = vec_to_boxed_array::<u16, 256>(0);
-    let mut buf_b = vec_to_boxed_array::<u16, 256>(0);
-    let mut buf_c = vec_to_boxed_array::<LzwEntry, 4096>(LzwEntry::default());
-    let mut buf_d = vec_to_boxed_array::<u8, REVERSE_BUF_LEN>(0);
-    let mut buf_e = vec_to_boxed_array::<u8, OUT_BUF_LEN>(0);
+#[test]
+fn interlaced_test() {
+    let path = "./tests/gifs/test_interlaced.gif";
+    let mut renderer = TestRenderer::new("interlaced_test");
 
-    let mut decoder = GifDecoder::new(
-        &mut data_source,
-        &mut renderer,
-        &mut *buf_a,
-        &mut *buf_b,
-        &mut *buf_c,
-        &mut *buf_d,
-        &mut *buf_e,
-    );
+    decode_file(path, &mut renderer);
 
+    assert_frames_match(&renderer.frames, &reference_frames(path));
+}
+
+#[test]
+fn gif87a_test() {
+    let path = "./tests/gifs/test_87a.gif";
+    let mut renderer = TestRenderer::new("gif87a_test");
+
+    let metadata = decode_file(path, &mut renderer);
+
+    assert_eq!(metadata.version, GifVersion::Gif87a);
+    assert_frames_match(&renderer.frames, &reference_frames(path));
+}
+
+#[test]
+fn disposal_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let mut renderer = TestRenderer::new_compositing("disposal_test");
+
+    decode_file(path, &mut renderer);
+
+    assert_frames_match(&renderer.frames, &reference_frames(path));
+}
+
+#[test]
+fn background_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let mut renderer = TestRenderer {
+        fill_background: true,
+        ..TestRenderer::new_compositing("background_test")
+    };
+
+    let metadata = decode_file(path, &mut renderer);
+    let background = rgba_from_565(metadata.background_color.unwrap());
+
+    assert_eq!(metadata.background_color_index, 2);
+    assert_eq!(background, Rgba([0, 252, 0, 0xFF]));
+
+    // the reference decoder leaves disposed areas transparent instead
+    let mut expected = reference_frames(path);
+    for pixel in expected.iter_mut().flat_map(|frame| frame.pixels_mut()) {
+        if pixel.0[3] == 0 {
+            *pixel = background;
+        }
+    }
+    assert_frames_match(&renderer.frames, &expected);
+}
+
+#[test]
+fn background_outside_table_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let mut bytes = read(path).unwrap();
+    assert_eq!(bytes[10] & 0b111, 2); // global color table of 8 colors
+    bytes[11] = 0x20;
+
+    let mut renderer = TestRenderer {
+        fill_background: true,
+        ..TestRenderer::new_compositing("background_outside_table_test")
+    };
+    let mut buffers = new_buffers();
+
+    // leaves a full color table of another file in the buffers
+    let large = read("./tests/gifs/test_large.gif").unwrap();
+    let mut decoder = buffers.decoder(SliceSource::new(&large), &mut renderer);
     decoder.parse_gif_metadata().unwrap();
 
-    loop {
-        match decoder.parse_frame_metadata() {
-            Ok(()) => decoder.decode_frame_image().unwrap(),
-            Err(Error::GifEnded) => break,
-            err => err.unwrap(),
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+    decoder.set_compositing(true);
+    decoder.set_fill_background(true);
+    decoder.parse_gif_metadata().unwrap();
+    assert_eq!(decoder.get_gif_metadata().unwrap().background_color, None);
+
+    while let Some(frame) = decoder.next_frame() {
+        frame.unwrap();
+        decoder.decode_frame_image().unwrap();
+    }
+
+    // nothing is filled
+    assert_frames_match(&renderer.frames, &reference_frames(path));
+}
+
+/// keeps what a Compositor sends, frames are only complete together with the earlier ones
+struct CanvasScreen {
+    screen: Screen,
+    frames: Vec<Screen>,
+    written_pixels: usize,
+    /// bounding box of the areas sent for each frame, in screen coordinates
+    regions: Vec<Option<(u32, u32, u32, u32)>>,
+    region: Option<(u32, u32, u32, u32)>,
+}
+
+impl CanvasScreen {
+    fn new() -> Self {
+        Self {
+            screen: ImageBuffer::new(SCREEN_SIZE as u32, SCREEN_SIZE as u32),
+            frames: Vec::new(),
+            written_pixels: 0,
+            regions: Vec::new(),
+            region: None,
+        }
+    }
+}
+
+/// grows the region (x_min, y_min, x_max, y_max) by a pixel
+fn extend_region(region: Option<(u32, u32, u32, u32)>, x: u32, y: u32) -> (u32, u32, u32, u32) {
+    match region {
+        Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        None => (x, y, x, y),
+    }
+}
+
+impl PixelRenderer for CanvasScreen {
+    type Format = Rgb565;
+    type Error = Error;
+
+    fn write_pixels(&mut self, area: ImageArea, pixels: &[u16]) -> Result<(), Error> {
+        for ((x, y), &color) in TestRenderer::screen_area(area).zip(pixels) {
+            self.screen.put_pixel(x, y, rgba_from_565(color));
+            self.region = Some(extend_region(self.region, x, y));
+        }
+        self.written_pixels += pixels.len();
+        Ok(())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), Error> {
+        self.frames.push(self.screen.clone());
+        self.regions.push(self.region.take());
+        Ok(())
+    }
+}
+
+#[test]
+fn compositor_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let mut canvas = vec![0u16; SCREEN_SIZE * SCREEN_SIZE];
+    let mut saved = vec![0u16; SCREEN_SIZE * SCREEN_SIZE];
+    let mut compositor = Compositor::new(
+        CanvasScreen::new(),
+        &mut canvas,
+        SCREEN_SIZE as u16,
+        SCREEN_SIZE as u16,
+    );
+    compositor.set_save_buffer(&mut saved);
+
+    decode_with(path, &mut compositor, true, false);
+    let downstream = compositor.into_renderer();
+
+    // the canvas is opaque, cleared areas are black
+    let mut expected = reference_frames(path);
+    for pixel in expected.iter_mut().flat_map(|frame| frame.pixels_mut()) {
+        if pixel.0[3] == 0 {
+            *pixel = rgba_from_565(0);
+        }
+    }
+    assert_frames_match(&downstream.frames, &expected);
+
+    // only the first frame is sent completely
+    let full_frames = downstream.frames.len() * SCREEN_SIZE * SCREEN_SIZE;
+    assert!(downstream.written_pixels < full_frames);
+
+    // afterwards the region of the pixels that changed
+    let last = SCREEN_SIZE as u32 - 1;
+    assert_eq!(downstream.regions[0], Some((0, 0, last, last)));
+
+    for (n, frames) in downstream.frames.windows(2).enumerate() {
+        let mut expected = None;
+        for (x, y, pixel) in frames[1].enumerate_pixels() {
+            if frames[0].get_pixel(x, y) != pixel {
+                expected = Some(extend_region(expected, x, y));
+            }
+        }
+        assert_eq!(downstream.regions[n + 1], expected, "frame {}", n + 1);
+    }
+}
+
+/// draws the spans with a TestRenderer, which then never sees a transparent pixel
+struct SpanScreen {
+    renderer: TestRenderer,
+    spans: usize,
+}
+
+impl SpanRenderer for SpanScreen {
+    type Format = Rgb565;
+    type Error = Error;
+
+    fn write_span(
+        &mut self,
+        xpos: u16,
+        ypos: u16,
+        indices: &[u8],
+        color_table: &[u16; 256],
+    ) -> Result<(), Error> {
+        assert!(!indices.is_empty());
+        self.spans += 1;
+
+        let area = ImageArea {
+            xpos,
+            ypos,
+            width: indices.len() as u16,
+            height: 1,
+        };
+        self.renderer.write_area(area, indices, color_table, None)
+    }
+
+    fn flush_frame(&mut self) -> Result<(), Error> {
+        self.renderer.flush_frame()
+    }
+
+    fn save_area(&mut self, area: ImageArea) -> Result<(), Error> {
+        self.renderer.save_area(area)
+    }
+
+    fn clear_area(&mut self, area: ImageArea) -> Result<(), Error> {
+        self.renderer.clear_area(area)
+    }
+
+    fn restore_area(&mut self, area: ImageArea) -> Result<(), Error> {
+        self.renderer.restore_area(area)
+    }
+}
+
+#[test]
+fn span_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let mut splitter = SpanSplitter::new(SpanScreen {
+        renderer: TestRenderer::new_compositing("span_test"),
+        spans: 0,
+    });
+
+    decode_with(path, &mut splitter, true, false);
+    let spans = splitter.into_renderer();
+
+    assert!(spans.spans > 0);
+    assert_frames_match(&spans.renderer.frames, &reference_frames(path));
+}
+
+/// records the position and length of the spans
+struct SpanLog(Vec<(u16, u16, usize)>);
+
+impl SpanRenderer for SpanLog {
+    type Format = Rgb565;
+    type Error = Error;
+
+    fn write_span(
+        &mut self,
+        xpos: u16,
+        ypos: u16,
+        indices: &[u8],
+        _color_table: &[u16; 256],
+    ) -> Result<(), Error> {
+        self.0.push((xpos, ypos, indices.len()));
+        Ok(())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[test]
+fn span_clip_test() {
+    // a 4x3 area in the corner of the coordinate range, index 0 is transparent
+    let mut splitter = SpanSplitter::new(SpanLog(Vec::new()));
+    let area = ImageArea {
+        xpos: u16::MAX - 2,
+        ypos: u16::MAX - 1,
+        width: 4,
+        height: 3,
+    };
+    let buffer = [1, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1];
+    splitter
+        .write_area(area, &buffer, &[0; 256], Some(0))
+        .unwrap();
+
+    let expected = [
+        (u16::MAX - 2, u16::MAX - 1, 1),
+        (u16::MAX, u16::MAX - 1, 1),
+        (u16::MAX - 2, u16::MAX, 3),
+    ];
+    assert_eq!(splitter.into_renderer().0, expected);
+}
+
+/// picks the source pixel of every destination pixel like the Scaler
+fn scale_frames(frames: &[Screen], numerator: u32, denominator: u32) -> Vec<Screen> {
+    frames
+        .iter()
+        .map(|frame| {
+            let width = (frame.width() * numerator).div_ceil(denominator);
+            let height = (frame.height() * numerator).div_ceil(denominator);
+            ImageBuffer::from_fn(width, height, |x, y| {
+                *frame.get_pixel(x * denominator / numerator, y * denominator / numerator)
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn scaler_test() {
+    // 40x29 scaled to 120x87, the buffer is too small for a whole row
+    let path = "./tests/gifs/test_interlaced.gif";
+    let mut buffer = [0u8; 100];
+    let mut scaler = Scaler::new(TestRenderer::new("scaler_test"), &mut buffer, 3);
+
+    decode_with(path, &mut scaler, false, false);
+    let expected = scale_frames(&reference_frames(path), 3, 1);
+    assert_frames_match(&scaler.into_renderer().frames, &expected);
+
+    // 32x32 shrunk to 22x22, including the disposal
+    let path = "./tests/gifs/test_disposal.gif";
+    let mut buffer = [0u8; 64];
+    let renderer = TestRenderer::new_compositing("scaler_ratio_test");
+    let mut scaler = Scaler::with_ratio(renderer, &mut buffer, 2, 3);
+
+    decode_with(path, &mut scaler, true, false);
+    let expected = scale_frames(&reference_frames(path), 2, 3);
+    assert_frames_match(&scaler.into_renderer().frames, &expected);
+
+    // without a buffer the pixels are passed one at a time
+    let renderer = TestRenderer::new_compositing("scaler_unbuffered_test");
+    let mut scaler = Scaler::new(renderer, &mut [], 2);
+
+    decode_with(path, &mut scaler, true, false);
+    let expected = scale_frames(&reference_frames(path), 2, 1);
+    assert_frames_match(&scaler.into_renderer().frames, &expected);
+}
+
+/// records the areas passed to write_area()
+struct AreaLog(Vec<ImageArea>);
+
+impl ImageRenderer for AreaLog {
+    type Format = Rgb565;
+    type Error = Error;
+
+    fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        _color_table: &[u16; 256],
+        _transparency_index: Option<u8>,
+    ) -> Result<(), Error> {
+        assert!(area.width > 0 && area.height > 0);
+        assert_eq!(buffer.len(), area.width as usize * area.height as usize);
+        self.0.push(area);
+        Ok(())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[test]
+fn scaler_limits_test() {
+    let pixel = |xpos, ypos, width, height| ImageArea {
+        xpos,
+        ypos,
+        width,
+        height,
+    };
+
+    // more copies of a row fit into the buffer than an area can hold
+    let mut buffer = vec![0u8; 65536];
+    let mut scaler = Scaler::new(AreaLog(Vec::new()), &mut buffer, 1);
+    scaler
+        .write_area(pixel(0, 0, 1, 1), &[1], &[0; 256], None)
+        .unwrap();
+    assert_eq!(scaler.into_renderer().0, [pixel(0, 0, 1, 1)]);
+
+    // the last row has a coordinate, the one after it is dropped
+    let mut buffer = [0u8; 4];
+    let mut scaler = Scaler::new(AreaLog(Vec::new()), &mut buffer, 1);
+    scaler
+        .write_area(pixel(0, u16::MAX - 1, 1, 3), &[1, 1, 1], &[0; 256], None)
+        .unwrap();
+    assert_eq!(scaler.into_renderer().0, [pixel(0, u16::MAX - 1, 1, 1)]);
+
+    // extreme ratios at the end of the coordinate range
+    let mut buffer = [0u8; 4];
+    let mut scaler = Scaler::with_ratio(AreaLog(Vec::new()), &mut buffer, u16::MAX, 1);
+    scaler
+        .write_area(pixel(u16::MAX - 2, 0, 10, 1), &[1; 10], &[0; 256], None)
+        .unwrap();
+    assert!(scaler.into_renderer().0.is_empty());
+
+    let mut scaler = Scaler::with_ratio(AreaLog(Vec::new()), &mut buffer, u16::MAX - 1, u16::MAX);
+    scaler
+        .write_area(pixel(u16::MAX - 2, 0, 3, 1), &[1, 1, 1], &[0; 256], None)
+        .unwrap();
+    assert_eq!(scaler.into_renderer().0, [pixel(u16::MAX - 2, 0, 2, 1)]);
+}
+
+#[test]
+fn transform_test() {
+    use image::imageops::{flip_horizontal, flip_vertical, rotate180, rotate270, rotate90};
+
+    type Case = (Rotation, bool, bool, fn(&Screen) -> Screen);
+    let cases: [Case; 6] = [
+        (Rotation::Deg90, false, false, |frame| rotate90(frame)),
+        (Rotation::Deg180, false, false, |frame| rotate180(frame)),
+        (Rotation::Deg270, false, false, |frame| rotate270(frame)),
+        (Rotation::Deg0, true, false, |frame| flip_horizontal(frame)),
+        (Rotation::Deg0, false, true, |frame| flip_vertical(frame)),
+        (Rotation::Deg90, true, false, |frame| {
+            rotate90(&flip_horizontal(frame))
+        }),
+    ];
+
+    // 40x29 interlaced and 32x32 with disposal, the buffer is smaller than a frame
+    // or missing, which passes single pixels
+    let files = [
+        ("./tests/gifs/test_interlaced.gif", 40, 29, false, 100),
+        ("./tests/gifs/test_disposal.gif", 32, 32, true, 100),
+        ("./tests/gifs/test_disposal.gif", 32, 32, true, 0),
+    ];
+
+    for (path, width, height, compositing, buffer_len) in files {
+        let reference = reference_frames(path);
+
+        for (n, (rotation, horizontal, vertical, transform)) in cases.into_iter().enumerate() {
+            let renderer = TestRenderer {
+                compositing,
+                ..TestRenderer::new(&format!("transform_test_{}", n))
+            };
+            let mut buffer = vec![0u8; buffer_len];
+            let mut transformed = Transform::new(renderer, &mut buffer, width, height);
+            transformed.set_rotation(rotation);
+            transformed.set_flip(horizontal, vertical);
+
+            decode_with(path, &mut transformed, compositing, false);
+
+            let expected: Vec<Screen> = reference.iter().map(transform).collect();
+            let (display_width, display_height) = transformed.display_size();
+            assert_eq!(display_width as u32, expected[0].width());
+            assert_eq!(display_height as u32, expected[0].height());
+            assert_frames_match(&transformed.into_renderer().frames, &expected);
         }
     }
 }
+
+#[test]
+fn image_too_big_test() {
+    let bytes = read("./tests/gifs/test_large.gif").unwrap();
+    let mut renderer = TestRenderer::new("image_too_big_test");
+
+    let mut buffers = Buffers::<u16, 128>::new(); // not enough for a single line
+
+    let mut decoder = buffers.decoder(bytes.into_iter(), &mut renderer);
+
+    let error = decoder.parse_gif_metadata().unwrap_err();
+    assert_eq!(
+        error,
+        DecodeError {
+            failure: Failure::Decode(Error::ImageTooBig(240)),
+            offset: 13,
+            frame: None,
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "width 240 does not fit into the output buffer at byte 13"
+    );
+}
+
+#[test]
+fn narrow_frame_test() {
+    // a red 1x2 image, more rows fit into the output buffer than an area can hold
+    let mut bytes = b"GIF89a\x01\x00\x02\x00\x80\x00\x00\xFF\x00\x00\x00\x00\x00".to_vec();
+    bytes.extend_from_slice(b"\x2C\x00\x00\x00\x00\x01\x00\x02\x00\x00");
+    bytes.extend_from_slice(b"\x02\x02\x04\x0A\x00\x3B");
+    let mut renderer = TestRenderer::new("narrow_frame_test");
+
+    let mut buffers = Buffers::<u16, 65536>::new();
+
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+    decoder.play(|_| true).unwrap();
+
+    assert_eq!(renderer.frames.len(), 1);
+    let red = rgba_from_565(0xF800);
+    assert_eq!(*renderer.frames[0].get_pixel(0, 0), red);
+    assert_eq!(*renderer.frames[0].get_pixel(0, 1), red);
+}
+
+/// draws the first frame of path onto a MockDisplay of the color C
+#[cfg(feature = "embedded-graphics")]
+fn assert_draw_target<C>(path: &str)
+where
+    C: embedded_graphics::pixelcolor::PixelColor
+        + From<embedded_graphics::pixelcolor::Rgb888>
+        + Default
+        + std::fmt::Debug,
+{
+    use embedded_gif::draw_target::DrawTargetRenderer;
+    use embedded_graphics::image::Image;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::pixelcolor::Rgb888;
+    use embedded_graphics::prelude::*;
+
+    let bytes = read(path).unwrap();
+    let offset = Point::new(10, 20);
+
+    let mut display = MockDisplay::<C>::new();
+    let mut renderer = DrawTargetRenderer::new(&mut display);
+
+    let mut buffers = Buffers::<C, 64>::new();
+
+    let mut decoder = buffers.decoder(bytes.into_iter(), &mut renderer);
+    decoder.parse_gif_metadata().unwrap();
+    decoder.parse_frame_metadata().unwrap();
+
+    let mut target = MockDisplay::<C>::new();
+    let frame = decoder.frame_image();
+    Image::new(&frame, offset).draw(&mut target).unwrap();
+    assert!(frame.take_error().is_none());
+
+    let expected = &reference_frames_rgba(path)[0];
+    for (x, y, pixel) in expected.enumerate_pixels() {
+        let [r, g, b, _] = pixel.0;
+        let point = Point::new(x as i32, y as i32) + offset;
+
+        assert_eq!(target.get_pixel(point), Some(C::from(Rgb888::new(r, g, b))));
+    }
+    assert_eq!(target.get_pixel(Point::new(0, 0)), None);
+    assert_eq!(
+        target.affected_area().size,
+        Size::new(expected.width(), expected.height())
+    );
+
+    // the image data is consumed by drawing it once
+    Image::new(&frame, offset).draw(&mut target).unwrap();
+    assert!(matches!(
+        frame.take_error(),
+        Some(DecodeError {
+            failure: Failure::Decode(Error::DecoderAlreadyFinished),
+            ..
+        })
+    ));
+}
+
+#[cfg(feature = "embedded-graphics")]
+#[test]
+fn embedded_graphics_test() {
+    use embedded_graphics::pixelcolor;
+
+    assert_draw_target::<pixelcolor::Rgb565>("./tests/gifs/test_87a.gif");
+    assert_draw_target::<pixelcolor::Gray8>("./tests/gifs/test_87a.gif");
+}
+
+/// keeps the last frame in the pixel format F
+struct PixelFormatRenderer<F: PixelFormat> {
+    width: usize,
+    screen: Vec<F::Pixel>,
+}
+
+impl<F: PixelFormat> ImageRenderer for PixelFormatRenderer<F> {
+    type Format = F;
+    type Error = Error;
+
+    fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<F>,
+        _transparency_index: Option<u8>,
+    ) -> Result<(), Error> {
+        let rows = buffer
+            .chunks(area.width as usize)
+            .take(area.height as usize);
+
+        for (y, row) in (area.ypos as usize..).zip(rows) {
+            for (x, &index) in (area.xpos as usize..).zip(row) {
+                self.screen[y * self.width + x] = color_table[index as usize];
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+fn assert_pixel_format<F>(path: &str)
+where
+    F: PixelFormat,
+    F::Pixel: PartialEq + std::fmt::Debug,
+{
+    let bytes = read(path).unwrap();
+    let expected = &reference_frames_rgba(path)[0];
+    let (width, height) = expected.dimensions();
+
+    let mut renderer = PixelFormatRenderer::<F> {
+        width: width as usize,
+        screen: vec![F::Pixel::default(); (width * height) as usize],
+    };
+
+    let mut buffers = Buffers::<F::Pixel>::new();
+
+    let mut decoder = buffers.decoder(bytes.into_iter(), &mut renderer);
+    decoder.parse_gif_metadata().unwrap();
+    decoder.parse_frame_metadata().unwrap();
+    decoder.decode_frame_image().unwrap();
+
+    for (x, y, pixel) in expected.enumerate_pixels() {
+        let [r, g, b, _] = pixel.0;
+        let actual = renderer.screen[(y * width + x) as usize];
+
+        assert_eq!(actual, F::from_rgb(r, g, b), "pixel at {} {}", x, y);
+    }
+}
+
+#[test]
+fn pixel_format_test() {
+    let path = "./tests/gifs/test_interlaced.gif";
+
+    assert_pixel_format::<Rgb565>(path);
+    assert_pixel_format::<Rgb565Be>(path);
+    assert_pixel_format::<Rgb565Le>(path);
+    assert_pixel_format::<Bgr565>(path);
+    assert_pixel_format::<Rgb666>(path);
+    assert_pixel_format::<Rgb888>(path);
+    assert_pixel_format::<Gray8>(path);
+
+    assert_eq!(Rgb565Be::from_rgb(0xFF, 0x00, 0x00), [0xF8, 0x00]);
+    assert_eq!(Rgb565Le::from_rgb(0xFF, 0x00, 0x00), [0x00, 0xF8]);
+    assert_eq!(Bgr565::from_rgb(0xFF, 0x00, 0x00), 0x001F);
+    assert_eq!(Rgb666::from_rgb(0xFF, 0x80, 0x03), [0xFC, 0x80, 0x00]);
+    assert_eq!(Gray8::from_rgb(0xFF, 0xFF, 0xFF), 0xFF);
+}
+
+#[test]
+fn loop_count_test() {
+    let mut renderer = TestRenderer::new("loop_count_test_large");
+    let metadata = decode_file("./tests/gifs/test_large.gif", &mut renderer);
+    assert_eq!(metadata.loop_count, LoopCount::Infinite);
+
+    let path = "./tests/gifs/test_loop.gif";
+    let mut renderer = TestRenderer::new("loop_count_test");
+
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.decoder(RewindableBytes::new(path), &mut renderer);
+
+    let mut delays = Vec::new();
+    decoder
+        .play(|delay| {
+            delays.push(delay);
+            true
+        })
+        .unwrap();
+
+    let metadata = decoder.get_gif_metadata().unwrap();
+    assert_eq!(metadata.loop_count, LoopCount::Repeat(2));
+    assert_eq!(delays, [20; 6]); // played three times
+
+    // stops when asked to
+    let mut frames = 0;
+    decoder
+        .play(|_| {
+            frames += 1;
+            frames < 3
+        })
+        .unwrap();
+    assert_eq!(frames, 3);
+    drop(decoder);
+
+    // both frames of every pass have been rendered
+    let reference = reference_frames(path);
+    assert_eq!(reference.len(), 2);
+    let expected: Vec<Screen> = reference.iter().cycle().take(6 + 3).cloned().collect();
+    assert_frames_match(&renderer.frames, &expected);
+
+    // an infinitely looping file without frames ends after the first pass
+    let mut empty = b"GIF89a\x08\x00\x08\x00\x00\x00\x00".to_vec();
+    empty.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00\x3B");
+    let mut renderer = TestRenderer::new("loop_count_test_empty");
+    let mut decoder = buffers.decoder(SliceSource::new(&empty), &mut renderer);
+    decoder.play(|_| panic!("no frame to wait for")).unwrap();
+    let metadata = decoder.get_gif_metadata().unwrap();
+    assert_eq!(metadata.loop_count, LoopCount::Infinite);
+
+    assert!(LoopCount::Infinite.should_repeat(u32::MAX));
+    assert!(!LoopCount::Once.should_repeat(1));
+}
+
+#[test]
+fn player_test() {
+    let mut renderer = TestRenderer::new("player_test");
+
+    let mut buffers = new_buffers();
+
+    let decoder = buffers.decoder(
+        RewindableBytes::new("./tests/gifs/test_loop.gif"),
+        &mut renderer,
+    );
+
+    let time = std::rc::Rc::new(std::cell::Cell::new(0u32));
+    let clock_time = time.clone();
+    let mut player = GifPlayer::new(decoder, move || clock_time.get());
+
+    let decoded = |next_in_ms| PlayerStatus::FrameDecoded { next_in_ms };
+    let waiting = |next_in_ms| PlayerStatus::Waiting { next_in_ms };
+
+    assert_eq!(player.poll().unwrap(), decoded(20));
+    time.set(5);
+    assert_eq!(player.poll().unwrap(), waiting(15));
+    time.set(23); // late frames are scheduled from when they were due
+    assert_eq!(player.poll().unwrap(), decoded(17));
+    time.set(40); // loops to the first frame
+    assert_eq!(player.poll().unwrap(), decoded(20));
+    time.set(500); // fell behind, restart the schedule
+    assert_eq!(player.poll().unwrap(), decoded(20));
+
+    // the time can also be passed in directly
+    assert_eq!(player.poll_at(510).unwrap(), waiting(10));
+    for t in [520, 540] {
+        assert_eq!(player.poll_at(t).unwrap(), decoded(20));
+    }
+    time.set(560); // played three times
+    assert_eq!(player.poll().unwrap(), PlayerStatus::Finished);
+    assert_eq!(player.poll().unwrap().next_in_ms(), None);
+
+    player.restart();
+    assert_eq!(player.poll().unwrap(), decoded(20));
+
+    drop(player);
+    assert_eq!(renderer.frames.len(), 7);
+
+    // an infinitely looping file without frames finishes after the first pass
+    let mut empty = b"GIF89a\x08\x00\x08\x00\x00\x00\x00".to_vec();
+    empty.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00\x3B");
+    let mut renderer = TestRenderer::new("player_test_empty");
+    let decoder = buffers.decoder(SliceSource::new(&empty), &mut renderer);
+    let mut player = GifPlayer::new(decoder, || 0);
+
+    assert_eq!(player.poll().unwrap(), PlayerStatus::Finished);
+    assert_eq!(player.poll().unwrap(), PlayerStatus::Finished);
+    player.restart();
+    assert_eq!(player.poll().unwrap(), PlayerStatus::Finished);
+}
+
+/// feeds the file in chunks of chunk_size bytes, returns the number of decoded frames
+fn push_decode_file(path: &str, renderer: &mut TestRenderer, chunk_size: usize) -> usize {
+    let bytes = read(path).unwrap();
+    let compositing = renderer.compositing;
+
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.push_decoder(renderer);
+    decoder.set_compositing(compositing);
+
+    let mut frames = 0;
+    for chunk in bytes.chunks(chunk_size) {
+        let mut chunk = chunk;
+        loop {
+            match decoder.feed(chunk).unwrap() {
+                Progress::NeedMoreData => break,
+                Progress::FrameDecoded { consumed } => {
+                    frames += 1;
+                    chunk = &chunk[consumed..];
+                }
+                Progress::Finished { consumed } => {
+                    assert_eq!(consumed, chunk.len());
+                    assert!(decoder.get_gif_metadata().is_some());
+                    return frames;
+                }
+            }
+        }
+    }
+    panic!("trailer not reached");
+}
+
+#[test]
+fn push_decoder_test() {
+    for path in [
+        "./tests/gifs/test_disposal.gif",
+        "./tests/gifs/test_interlaced.gif",
+        "./tests/gifs/test_loop.gif",
+    ] {
+        let expected = reference_frames(path);
+
+        // chunks end mid-header, mid-color-table and mid-sub-block
+        for chunk_size in [1, 5, 64, 100_000] {
+            let mut renderer = TestRenderer::new_compositing("push_decoder_test");
+
+            let frames = push_decode_file(path, &mut renderer, chunk_size);
+
+            assert_eq!(frames, expected.len());
+            assert_frames_match(&renderer.frames, &expected);
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+struct AsyncTestRenderer<'r>(&'r mut TestRenderer);
+
+#[cfg(feature = "async")]
+impl<'r> embedded_gif::async_decoder::AsyncImageRenderer for AsyncTestRenderer<'r> {
+    type Format = Rgb565;
+    type Error = Error;
+
+    async fn write_area(
+        &mut self,
+        area: ImageArea,
+        buffer: &[u8],
+        color_table: &ColorTable<Rgb565>,
+        transparency_index: Option<u8>,
+    ) -> Result<(), Error> {
+        self.0
+            .write_area(area, buffer, color_table, transparency_index)
+    }
+
+    async fn flush_frame(&mut self) -> Result<(), Error> {
+        self.0.flush_frame()
+    }
+
+    async fn save_area(&mut self, area: ImageArea) -> Result<(), Error> {
+        self.0.save_area(area)
+    }
+
+    async fn clear_area(&mut self, area: ImageArea) -> Result<(), Error> {
+        self.0.clear_area(area)
+    }
+
+    async fn restore_area(&mut self, area: ImageArea) -> Result<(), Error> {
+        self.0.restore_area(area)
+    }
+}
+
+/// runs a future whose reader and renderer never actually wait
+#[cfg(feature = "async")]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(std::ptr::null(), &VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+    let waker = unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_decoder_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let bytes = read(path).unwrap();
+    let mut renderer = TestRenderer::new_compositing("async_decoder_test");
+    let mut async_renderer = AsyncTestRenderer(&mut renderer);
+
+    let mut read_buffer = [0u8; 7]; // reads end mid-field
+    let mut buffers = new_buffers();
+
+    let mut decoder =
+        buffers.async_decoder(bytes.as_slice(), &mut read_buffer, &mut async_renderer);
+    decoder.set_compositing(true);
+
+    block_on(async {
+        decoder.parse_gif_metadata().await.unwrap();
+
+        loop {
+            match decoder.parse_frame_metadata().await {
+                Ok(()) => decoder.decode_frame_image().await.unwrap(),
+                Err(error) if error.error() == Error::GifEnded => break,
+                err => err.unwrap(),
+            }
+        }
+    });
+    assert_eq!(decoder.get_gif_metadata().unwrap().width, 32);
+
+    assert_frames_match(&renderer.frames, &reference_frames(path));
+
+    // the same as a ReadSource without buffer
+    let mut async_renderer = AsyncTestRenderer(&mut renderer);
+    let mut decoder = buffers.async_decoder(bytes.as_slice(), &mut [], &mut async_renderer);
+    let error = block_on(decoder.parse_gif_metadata()).unwrap_err();
+    assert_eq!(error.error(), Error::ReadError);
+}
+
+/// in-memory file that counts the read calls and fails reading at byte fail_at
+#[cfg(feature = "embedded-io")]
+struct CountingFile {
+    bytes: Vec<u8>,
+    position: usize,
+    reads: usize,
+    fail_at: usize,
+}
+
+#[cfg(feature = "embedded-io")]
+impl CountingFile {
+    fn new(path: &str, fail_at: usize) -> Self {
+        Self {
+            bytes: read(path).unwrap(),
+            position: 0,
+            reads: 0,
+            fail_at,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::ErrorType for CountingFile {
+    type Error = embedded_io::ErrorKind;
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Read for CountingFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.position >= self.fail_at {
+            return Err(embedded_io::ErrorKind::Other);
+        }
+        let count = buf.len().min(self.bytes.len() - self.position);
+        buf[..count].copy_from_slice(&self.bytes[self.position..self.position + count]);
+        self.position += count;
+        self.reads += 1;
+        Ok(count)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Seek for CountingFile {
+    fn seek(&mut self, pos: embedded_io::SeekFrom) -> Result<u64, Self::Error> {
+        self.position = match pos {
+            embedded_io::SeekFrom::Start(offset) => offset as usize,
+            embedded_io::SeekFrom::End(offset) => (self.bytes.len() as i64 + offset) as usize,
+            embedded_io::SeekFrom::Current(offset) => (self.position as i64 + offset) as usize,
+        };
+        Ok(self.position as u64)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+#[test]
+fn read_source_test() {
+    use embedded_gif::read_source::ReadSource;
+
+    let path = "./tests/gifs/test_loop.gif";
+    let file = CountingFile::new(path, usize::MAX);
+    let mut renderer = TestRenderer::new("read_source_test");
+
+    let mut read_buffer = [0u8; 16];
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.decoder(ReadSource::new(file, &mut read_buffer), &mut renderer);
+
+    let mut frames = 0;
+    decoder
+        .play(|_| {
+            frames += 1;
+            true
+        })
+        .unwrap();
+    assert_eq!(frames, 6); // rewound twice
+
+    let data_source = decoder.get_data_source();
+    assert!(data_source.take_error().is_none());
+    let file = data_source.get_reader();
+    assert_eq!(file.reads, 3 * file.bytes.len().div_ceil(16));
+
+    let mut index = [FrameIndexEntry::default(); 2];
+    assert_eq!(decoder.build_frame_index(&mut index).unwrap(), 2);
+    assert!(index[0].offset < index[1].offset);
+
+    assert_frames_match(&renderer.frames[..2], &reference_frames(path));
+
+    // a failing read is reported as such instead of as the end of the file
+    let file = CountingFile::new(path, 48);
+    let mut decoder = buffers.decoder(ReadSource::new(file, &mut read_buffer), &mut renderer);
+    let error = decoder.play(|_| true).unwrap_err();
+    assert_eq!(error.error(), Error::ReadError);
+    let data_source = decoder.get_data_source();
+    assert_eq!(
+        data_source.take_error(),
+        Some(embedded_io::ErrorKind::Other)
+    );
+
+    let file = CountingFile::new(path, usize::MAX);
+    let mut decoder = buffers.decoder(ReadSource::new(file, &mut []), &mut renderer);
+    let error = decoder.parse_gif_metadata().unwrap_err();
+    assert_eq!(error.error(), Error::ReadError);
+}
+
+#[test]
+fn slice_source_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let bytes = read(path).unwrap();
+    let mut renderer = TestRenderer::new_compositing("slice_source_test");
+
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+    decoder.set_compositing(true);
+    decoder.play(|_| true).unwrap();
+    assert_eq!(decoder.get_data_source().position(), bytes.len());
+
+    // a truncated sub-block ends the file
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes[..bytes.len() - 10]), &mut renderer);
+    decoder.parse_gif_metadata().unwrap();
+    let result = loop {
+        match decoder.parse_frame_metadata() {
+            Ok(()) => {}
+            err => break err,
+        }
+        if let Err(error) = decoder.decode_frame_image() {
+            break Err(error);
+        }
+    };
+    assert_eq!(result.unwrap_err().error(), Error::FileEnded);
+
+    assert_frames_match(&renderer.frames[..5], &reference_frames(path));
+}
+
+#[test]
+fn frame_index_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let bytes = read(path).unwrap();
+
+    // every frame on its own, for comparison
+    let mut sequential = TestRenderer::new("frame_index_test_sequential");
+    decode_file(path, &mut sequential);
+
+    let mut renderer = TestRenderer::new("frame_index_test");
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+    decoder.parse_gif_metadata().unwrap();
+    let position = decoder.get_data_source().position();
+
+    let mut table = [FrameIndexEntry::default(); 8];
+    let frame_count = decoder.build_frame_index(&mut table).unwrap();
+    assert_eq!(frame_count, 5);
+    let index = &table[..frame_count];
+    assert_eq!(decoder.get_data_source().position(), position);
+
+    let disposal_methods: Vec<_> = index.iter().map(|e| e.disposal_method).collect();
+    assert_eq!(
+        disposal_methods,
+        [
+            DisposalMethod::DoNotDispose,
+            DisposalMethod::RestoreToBackground,
+            DisposalMethod::RestoreToPrevious,
+            DisposalMethod::Unspecified,
+            DisposalMethod::DoNotDispose,
+        ]
+    );
+    assert!(index
+        .windows(2)
+        .take(4)
+        .all(|pair| pair[0].offset < pair[1].offset));
+
+    // a short table only holds the first frames
+    let mut short_index = [FrameIndexEntry::default(); 2];
+    assert_eq!(decoder.build_frame_index(&mut short_index).unwrap(), 5);
+    assert_eq!(short_index, index[..2]);
+
+    for n in [3, 0, 4] {
+        decoder.seek_to_frame(index, n).unwrap();
+        decoder.parse_frame_metadata().unwrap();
+        assert_eq!(
+            decoder.get_current_frame_delay_ms(),
+            Some(index[n].delay_ms)
+        );
+        decoder.decode_frame_image().unwrap();
+    }
+    assert_eq!(
+        decoder.seek_to_frame(index, 5).unwrap_err().error(),
+        Error::FrameNotFound
+    );
+    let error = decoder.parse_frame_metadata().unwrap_err();
+    assert_eq!(error.error(), Error::GifEnded);
+    assert_eq!(error.offset, bytes.len());
+    assert_eq!(error.frame, Some(5));
+
+    let expected: Vec<_> = [3, 0, 4]
+        .iter()
+        .map(|&n| sequential.frames[n].clone())
+        .collect();
+    assert_frames_match(&renderer.frames, &expected);
+}
+
+#[test]
+fn skip_frame_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+
+    let mut sequential = TestRenderer::new("skip_frame_test_sequential");
+    decode_file(path, &mut sequential);
+
+    let mut renderer = TestRenderer::new("skip_frame_test");
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.decoder(RewindableBytes::new(path), &mut renderer);
+    decoder.parse_gif_metadata().unwrap();
+
+    // only every second frame is drawn
+    let mut frame = 0;
+    loop {
+        match decoder.parse_frame_metadata() {
+            Ok(()) if frame % 2 == 0 => decoder.skip_frame_image().unwrap(),
+            Ok(()) => decoder.decode_frame_image().unwrap(),
+            Err(error) if error.error() == Error::GifEnded => break,
+            err => err.unwrap(),
+        }
+        frame += 1;
+    }
+    assert_eq!(frame, 5);
+
+    let expected = [sequential.frames[1].clone(), sequential.frames[3].clone()];
+    assert_frames_match(&renderer.frames, &expected);
+}
+
+#[test]
+fn typed_decoder_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let bytes = read(path).unwrap();
+    let mut renderer = TestRenderer::new_compositing("typed_decoder_test");
+
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+    // the unchecked decoder reports a missing frame instead of panicking
+    decoder.parse_gif_metadata().unwrap();
+    assert_eq!(
+        decoder.decode_frame_image().unwrap_err().error(),
+        Error::MissingFrameMetadata
+    );
+    decoder.rewind().unwrap();
+
+    let mut decoder = TypedGifDecoder::new(decoder);
+    decoder.set_compositing(true);
+
+    let mut frame_count = 0;
+    let mut decoder = decoder.parse_gif_metadata().unwrap();
+    let decoder = loop {
+        match decoder.next_frame().unwrap() {
+            NextFrame::Frame(frame) => {
+                assert!(frame.get_current_frame_delay_ms().is_some());
+                decoder = frame.decode_frame_image().unwrap();
+                frame_count += 1;
+            }
+            NextFrame::Ended(trailer) => break trailer,
+        }
+    };
+    assert_eq!(frame_count, 5);
+    assert_eq!(decoder.get_gif_metadata().unwrap().width, 32);
+
+    // skipping all frames of a second run does not call the renderer
+    let mut decoder = decoder.rewind().unwrap().parse_gif_metadata().unwrap();
+    while let NextFrame::Frame(frame) = decoder.next_frame().unwrap() {
+        decoder = frame.skip_frame_image().unwrap();
+    }
+
+    assert_frames_match(&renderer.frames, &reference_frames(path));
+}
+
+#[test]
+fn next_frame_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+
+    let mut sequential = TestRenderer::new("next_frame_test_sequential");
+    decode_file(path, &mut sequential);
+
+    let mut renderer = TestRenderer::new("next_frame_test");
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.decoder(RewindableBytes::new(path), &mut renderer);
+    decoder.parse_gif_metadata().unwrap();
+
+    // frames that are neither decoded nor skipped are skipped by the next call
+    let mut frames: Vec<FrameInfo> = Vec::new();
+    while let Some(frame) = decoder.next_frame() {
+        if frames.len() % 2 == 1 {
+            decoder.decode_frame_image().unwrap();
+        }
+        frames.push(frame.unwrap());
+    }
+    assert!(decoder.next_frame().is_none());
+    assert_eq!(frames.len(), 5);
+    assert_eq!(
+        frames[1].disposal_method,
+        DisposalMethod::RestoreToBackground
+    );
+    assert!(frames.iter().all(|frame| frame.frame_area.width <= 32));
+
+    decoder.rewind().unwrap();
+    decoder.parse_gif_metadata().unwrap();
+    assert_eq!(decoder.next_frame().unwrap().unwrap(), frames[0]);
+
+    let expected = [sequential.frames[1].clone(), sequential.frames[3].clone()];
+    assert_frames_match(&renderer.frames, &expected);
+
+    // a truncated file returns a single error, the calls after it return None
+    let bytes = read(path).unwrap();
+    let truncated = &bytes[..bytes.len() / 2];
+    let mut decoder = buffers.decoder(SliceSource::new(truncated), &mut renderer);
+    decoder.parse_gif_metadata().unwrap();
+
+    let results: Vec<_> = (0..10).map_while(|_| decoder.next_frame()).collect();
+    let (last, frames) = results.split_last().unwrap();
+    assert!(frames.iter().all(|frame| frame.is_ok()));
+    assert_eq!(last.unwrap_err().error(), Error::FileEnded);
+    assert!(decoder.next_frame().is_none());
+}
+
+#[test]
+fn error_position_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let mut bytes = read(path).unwrap();
+    let mut renderer = TestRenderer::new("error_position_test");
+
+    let mut buffers = new_buffers();
+
+    let mut table = [FrameIndexEntry::default(); 8];
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+    decoder.build_frame_index(&mut table).unwrap();
+
+    // break the first block of the third frame
+    let offset = table[2].offset;
+    bytes[offset] = 0x42;
+    let expected = DecodeError {
+        failure: Failure::Decode(Error::InvalidBlockintroducer(0x42)),
+        offset: offset + 1,
+        frame: Some(2),
+    };
+
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+    decoder.parse_gif_metadata().unwrap();
+    let error = loop {
+        match decoder.next_frame() {
+            Some(Ok(_)) => decoder.decode_frame_image().unwrap(),
+            Some(Err(error)) => break error,
+            None => panic!("broken file decoded"),
+        }
+    };
+    assert_eq!(error, expected);
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "invalid block introducer 0x42 at byte {} in frame 2",
+            offset + 1
+        )
+    );
+    // the message already names the decoding error, there is no source to repeat it
+    assert!(std::error::Error::source(&error).is_none());
+
+    let mut decoder = buffers.push_decoder(&mut renderer);
+    let mut chunk = &bytes[..];
+    let error = loop {
+        match decoder.feed(chunk) {
+            Ok(Progress::FrameDecoded { consumed }) => chunk = &chunk[consumed..],
+            Ok(progress) => panic!("broken file decoded: {:?}", progress),
+            Err(error) => break error,
+        }
+    };
+    assert_eq!(error, expected);
+}
+
+#[test]
+fn lzw_code_size_test() {
+    let path = "./tests/gifs/test_disposal.gif";
+    let bytes = read(path).unwrap();
+    let mut renderer = TestRenderer::new("lzw_code_size_test");
+
+    let mut buffers = new_buffers();
+
+    let mut table = [FrameIndexEntry::default(); 8];
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+    decoder.build_frame_index(&mut table).unwrap();
+
+    // the first frame has a graphics control extension and no local color table
+    let offset = table[0].offset + 8 + 10;
+    for size in [1, 12, 40] {
+        let mut bytes = bytes.clone();
+        bytes[offset] = size;
+        let expected = DecodeError {
+            failure: Failure::Decode(Error::InvalidLzwCodeSize(size)),
+            offset: offset + 1,
+            frame: Some(0),
+        };
+
+        let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+        decoder.parse_gif_metadata().unwrap();
+        decoder.next_frame().unwrap().unwrap();
+        assert_eq!(decoder.decode_frame_image(), Err(expected));
+
+        let mut decoder = buffers.push_decoder(&mut renderer);
+        assert_eq!(decoder.feed(&bytes), Err(expected));
+    }
+}
+
+/// bus error of FailingRenderer
+#[derive(Debug, PartialEq)]
+struct BusTimeout {
+    ypos: u16,
+}
+
+impl std::fmt::Display for BusTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bus timeout in row {}", self.ypos)
+    }
+}
+
+impl std::error::Error for BusTimeout {}
+
+/// fails when writing the row fail_at of the second frame
+struct FailingRenderer {
+    frames: usize,
+    fail_at: u16,
+}
+
+impl ImageRenderer for FailingRenderer {
+    type Format = Rgb565;
+    type Error = BusTimeout;
+
+    fn write_area(
+        &mut self,
+        area: ImageArea,
+        _buffer: &[u8],
+        _color_table: &ColorTable<Self::Format>,
+        _transparency_index: Option<u8>,
+    ) -> Result<(), BusTimeout> {
+        let rows = area.ypos..area.ypos + area.height;
+        match self.frames == 1 && rows.contains(&self.fail_at) {
+            true => Err(BusTimeout { ypos: area.ypos }),
+            false => Ok(()),
+        }
+    }
+
+    fn flush_frame(&mut self) -> Result<(), BusTimeout> {
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn render_error_test() {
+    let bytes = read("./tests/gifs/test_disposal.gif").unwrap();
+    let mut renderer = FailingRenderer {
+        frames: 0,
+        fail_at: 10,
+    };
+
+    let mut buffers = new_buffers();
+
+    let mut decoder = buffers.decoder(SliceSource::new(&bytes), &mut renderer);
+    let error = decoder.play(|_| true).unwrap_err();
+
+    assert_eq!(error.error(), Error::RenderError);
+    assert_eq!(error.frame, Some(1));
+    let bus_error = error.render_error().unwrap();
+    assert!(bus_error.ypos <= 10);
+
+    // reporters walking the chain print the bus error once, as the source
+    let message = format!("renderer failed at byte {} in frame 1", error.offset);
+    assert_eq!(error.to_string(), message);
+    let source = std::error::Error::source(&error).unwrap();
+    assert_eq!(source.to_string(), bus_error.to_string());
+}